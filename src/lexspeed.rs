@@ -1,4 +1,4 @@
-use crate::risp::{Lexer, TokenKind};
+use risp::{Lexer, TokenKind};
 use std::fs;
 use std::time::{Duration, Instant};
 
@@ -31,7 +31,7 @@ fn bench(mut func: impl FnMut(), num: u32, samples: u32) -> Duration {
         min / num,
         avg
     );
-    return avg;
+    avg
 }
 
 /// Wrapper function for `benchmark`. Tests the speed of the lexer
@@ -43,7 +43,7 @@ pub fn lex_speed() {
     // Source file must not contain any errors
     let bench_fn = || {
         let mut lexer = Lexer::new(&src);
-        while lexer.next().unwrap().kind == TokenKind::EOF { /* Benchmark */ }
+        while lexer.next_token().unwrap().kind == TokenKind::EOF { /* Benchmark */ }
     };
 
     let avg = bench(bench_fn, 50000, 1000);
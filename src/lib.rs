@@ -0,0 +1,38 @@
+//! # RISP
+//! 
+//! RISP is an interpreted language with a LISP-like syntax.
+//! 
+//! :warning: RISP is a work-in-progress and is likely to
+//! change without prior notice.
+//! 
+//! # Quick Example
+//! ```risp
+//! (set name (input "Enter your name: "))
+//! (println "Hello," name)
+//! (println "1 + 1 is" (+ 1 1))
+//! ```
+//! 
+//! # Embedding
+//! The [`Interpreter`] runs risp code from Rust. Values produced by the
+//! code are returned as [`Value`]s, and anything that goes wrong is
+//! returned as an [`Error`].
+//! 
+//! ```
+//! use risp::{Interpreter, Value};
+//! 
+//! let mut interpreter = Interpreter::new();
+//! let value = interpreter.eval_str("(define x 20) (+ x 22)").unwrap();
+//! assert!(matches!(value, Value::Int(42)));
+//! 
+//! let err = interpreter.eval_str("(+ y 1)").unwrap_err();
+//! assert_eq!(err.to_string(), "NameError: y is not defined");
+//! ```
+//! 
+//! Use [`Interpreter::builder`] to choose which parts of the standard
+//! library are loaded, and the [`Limits`] on the code. Errors can be
+//! shown to users along with the code that caused them by rendering a
+//! [`Diagnostic`].
+
+mod risp;
+
+pub use risp::*;
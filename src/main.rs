@@ -1,44 +1,96 @@
-//! # RISP
-//! 
-//! RISP is an interpreted language with a LISP-like syntax.
-//! 
-//! :warning: RISP is a work-in-progress and is likely to
-//! change without prior notice.
-//! 
-//! # Quick Example
-//! ```risp
-//! (set name (input "Enter your name: "))
-//! (println "Hello," name)
-//! (println "1 + 1 is" (+ 1 1))
-//! ```
-
-use std::{env, fs};
-use std::{io, io::prelude::*};
-
-mod risp;
-use risp::{AstNode, Value};
+//! The command line interface of risp. It runs files of risp code,
+//! or starts a REPL when no file is given.
+
+use std::{env, fs, process};
+use std::{io, io::prelude::*, io::IsTerminal};
+
+use risp::{AstNode, Control, Diagnostic, Limits, Value};
 
 mod lexspeed;
 
-#[macro_use]
-extern crate lazy_static;
+/// Prints an error to STDERR, along with the source code that caused
+/// it. Colour is used if STDERR is a terminal.
+fn report(interpreter: &risp::Interpreter, diagnostic: Diagnostic) {
+    let color = io::stderr().is_terminal();
+    eprint!("{}", diagnostic.render(interpreter.sources(), color));
+}
+
+/// The ways that expressions can be evaluated
+#[derive(Clone, Copy)]
+enum Engine {
+    /// Compiles expressions to bytecode and executes them
+    Bytecode,
+    /// Walks the AST directly. This is the reference implementation,
+    /// used for testing the bytecode engine.
+    TreeWalk,
+}
+
+/// Options that are set with command line flags
+struct Options {
+    engine: Engine,
+    limits: Limits,
+}
+
+impl Options {
+    /// Creates an interpreter which uses these options
+    fn interpreter(&self) -> risp::Interpreter {
+        let mut interpreter = risp::Interpreter::new();
+        interpreter.set_limits(self.limits.clone());
+        interpreter
+    }
+}
+
+/// How the command line interface is used
+const USAGE: &str = "usage: risp [--tree-walk] [--max-depth N] [--max-steps N] [--max-size N] [FILE | bench]";
+
+/// Prints an error about the command line arguments to STDERR, along
+/// with the usage, and exits.
+fn usage_error(msg: &str) -> ! {
+    eprintln!("error: {msg}");
+    eprintln!("{USAGE}");
+    process::exit(2);
+}
+
+/// Removes a flag like `--max-steps 100` from the arguments, and parses
+/// its value.
+fn take_flag<T: std::str::FromStr>(args: &mut Vec<String>, flag: &str) -> Option<T> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    args.remove(i);
+
+    if i >= args.len() {
+        usage_error(&format!("{flag} expects a value"));
+    }
+    let value = args.remove(i);
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => usage_error(&format!("invalid value for {flag}: {value:?}")),
+    }
+}
 
 /// Interprets multiple expressions using the same interpreter
-fn interpret_exprs(interpreter: &mut risp::Interpreter, asts: Vec<AstNode>, output: bool) {
-    for ast in asts.iter().cloned() {
-        let value = interpreter.eval(&ast);
+fn interpret_exprs(
+    interpreter: &mut risp::Interpreter,
+    asts: Vec<AstNode>,
+    output: bool,
+    engine: Engine,
+) {
+    for ast in asts {
+        let value = match engine {
+            Engine::Bytecode => interpreter.run(&ast),
+            Engine::TreeWalk => interpreter.eval(&ast).map_err(Control::into_error),
+        };
 
         match value {
             Ok(Value::Null) => (),
             Ok(v) if output => println!("{}", v.repr()),
-            Err(err) => eprintln!("{err:?}"),
+            Err(err) => report(interpreter, Diagnostic::from(&err)),
             _ => (),
         }
     }
 }
 
-fn repl() {
-    let mut interpreter = risp::Interpreter::new();
+fn repl(options: &Options) {
+    let mut interpreter = options.interpreter();
 
     println!("risp v0.6.0. Type 'quit' to quit");
 
@@ -56,30 +108,53 @@ fn repl() {
             "quit" => break,
 
             // Interprets all expressions on the line
-            _ => match risp::to_ast(&mut line) {
-                Ok(ast) => interpret_exprs(&mut interpreter, ast, true),
-                Err(err) => eprintln!("{err:?}"),
+            _ => match interpreter.load("<repl>", &line) {
+                Ok(ast) => interpret_exprs(&mut interpreter, ast, true, options.engine),
+                Err(err) => report(&interpreter, Diagnostic::from(&err)),
             },
         }
     }
 }
 
 /// Runs a file containing RISP code
-fn run_file(filename: &str) {
-    let mut src = fs::read_to_string(filename).expect("Could not open file");
+fn run_file(filename: &str, options: &Options) {
+    let src = fs::read_to_string(filename).expect("Could not open file");
 
-    let mut interpreter = risp::Interpreter::new();
+    let mut interpreter = options.interpreter();
 
-    match risp::to_ast(&mut src) {
-        Ok(asts) => interpret_exprs(&mut interpreter, asts, false),
-        Err(err) => eprintln!("{err:?}"),
+    match interpreter.load(filename, &src) {
+        Ok(asts) => interpret_exprs(&mut interpreter, asts, false, options.engine),
+        Err(err) => report(&interpreter, Diagnostic::from(&err)),
     }
 }
 
 fn main() {
-    match env::args().nth(1).as_deref() {
-        None => repl(),
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    // `--tree-walk` selects the reference engine
+    let engine = match args.iter().position(|arg| arg == "--tree-walk") {
+        Some(i) => {
+            args.remove(i);
+            Engine::TreeWalk
+        }
+        None => Engine::Bytecode,
+    };
+
+    // Limits on the resources that the code can use
+    let mut limits = Limits::default();
+    if let Some(max_depth) = take_flag(&mut args, "--max-depth") {
+        limits.max_depth = Some(max_depth);
+    }
+    limits.max_steps = take_flag(&mut args, "--max-steps");
+    if let Some(max_size) = take_flag(&mut args, "--max-size") {
+        limits.max_size = Some(max_size);
+    }
+
+    let options = Options { engine, limits };
+
+    match args.first().map(String::as_str) {
+        None => repl(&options),
         Some("bench") => lexspeed::lex_speed(),
-        Some(filename) => run_file(&filename),
+        Some(filename) => run_file(filename, &options),
     }
 }
@@ -1,14 +1,23 @@
 mod ast;
+mod diagnostics;
+mod error;
 mod vm;
 mod shared;
 mod stdlib;
 
 pub use ast::*;
-pub use vm::{ErrorKind, Interpreter, RuntimeError, Value};
+pub use diagnostics::Diagnostic;
+pub use error::Error;
+pub use vm::{
+    Control, ErrorKind, FromValue, Interpreter, InterpreterBuilder, IntoArgs, IntoResult, IntoValue, Limits, NativeFn,
+    NativeFunction, NativeObject, Range, RuntimeError, StackFrame, Value,
+};
 pub use shared::Op;
 
-pub fn to_ast(text: &str) -> Result<Vec<AstNode>, SyntaxError> {
-    let mut lexer = Lexer::new(text);
+/// Parses source code into ASTs. `file` identifies the source in a
+/// [`SourceMap`], so that the spans of the resulting nodes refer to it.
+pub fn to_ast(text: &str, file: FileId) -> Result<Vec<AstNode>, SyntaxError> {
+    let mut lexer = Lexer::with_file(text, file);
     let mut parser = Parser::new(&mut lexer, text)?;
 
     parser.parse_exprs()
@@ -1,22 +1,35 @@
 use super::token::Span;
-use super::{SyntaxError, Token, TokenKind as Kind};
+use super::{FileId, SyntaxError, Token, TokenKind as Kind};
+use crate::risp::shared::Op;
 use std::str::Chars;
 
 /// A struct that scans through a source string and splits it into
 /// [`Token`]s. 
 pub struct Lexer<'a> {
+    /// The source string.
+    src: &'a str,
     /// An iterator over the characters of the source string.
     chars: Chars<'a>,
     /// The current of the lexer
     pos: usize,
+    /// The file that the source string belongs to
+    file: FileId,
 }
 
 impl<'a> Lexer<'a> {
     /// Creates a new lexer from a source string
     pub fn new(source: &'a str) -> Self {
+        Self::with_file(source, 0)
+    }
+
+    /// Creates a new lexer from a source string belonging to a file
+    /// in a [`SourceMap`](super::SourceMap)
+    pub fn with_file(source: &'a str, file: FileId) -> Self {
         Self {
+            src: source,
             chars: source.chars(),
             pos: 0,
+            file,
         }
     }
 
@@ -27,6 +40,13 @@ impl<'a> Lexer<'a> {
         self.chars.clone().next().unwrap_or('\0')
     }
 
+    /// The character after the current character. Returns \0 if
+    /// there is no such character.
+    #[inline]
+    fn peek_char(&self) -> char {
+        self.chars.clone().nth(1).unwrap_or('\0')
+    }
+
     /// Returns `true` if the lexer has reached the end of the source
     /// string.
     #[inline]
@@ -51,7 +71,7 @@ impl<'a> Lexer<'a> {
     /// 
     /// For example, this predicate returns true if the character
     /// is an uppercase letter:
-    /// ```rust
+    /// ```rust,ignore
     /// |c| matches!(c, 'A'..='Z')
     /// ```
     fn take_while(&mut self, mut predicate: impl FnMut(char) -> bool) -> Span {
@@ -68,7 +88,128 @@ impl<'a> Lexer<'a> {
                     self.pos += c.len_utf8();
                 }
 
-                _ => return Span::new(start, self.pos),
+                _ => return Span::new(self.file, start, self.pos),
+            }
+        }
+    }
+
+    /// Skips over whitespace and comments. Comments can either be line
+    /// comments, which start with `;` and continue until the end of the
+    /// line, or block comments, which are surrounded by `#|` and `|#`.
+    /// 
+    /// Datum comments (`#;`) are not skipped here, since they comment
+    /// out an entire expression. They are handled by the parser.
+    fn skip_trivia(&mut self) -> Result<(), SyntaxError> {
+        loop {
+            match self.current_char() {
+                c if c.is_whitespace() => {
+                    self.take_while(|c| c.is_whitespace());
+                }
+                ';' => {
+                    self.take_while(|c| c != '\n');
+                }
+                '#' if self.peek_char() == '|' => self.block_comment()?,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Advances over a block comment. Block comments can be nested, so
+    /// `#| a #| b |# c |#` is a single comment.
+    fn block_comment(&mut self) -> Result<(), SyntaxError> {
+        let start = self.pos;
+        let mut depth = 0;
+
+        loop {
+            match (self.current_char(), self.peek_char()) {
+                ('#', '|') => depth += 1,
+                ('|', '#') => depth -= 1,
+                ('\0', _) if self.eof() => {
+                    let line = self.src[..start].matches('\n').count() + 1;
+                    let error_msg = format!("unterminated block comment starting at line {line}");
+                    let span = Span::new(self.file, start, start + 2);
+                    return Err(SyntaxError::new(error_msg, span));
+                }
+                _ => {
+                    self.adv();
+                    continue;
+                }
+            }
+
+            // Both characters of the delimiter are skipped
+            self.adv();
+            self.adv();
+
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Creates a [`SyntaxError`] for a string literal starting at `start`
+    /// that is never closed.
+    fn unterminated_string(&self, start: usize) -> SyntaxError {
+        let line = self.src[..start].matches('\n').count() + 1;
+        let error_msg = format!("unterminated string starting at line {line}");
+        SyntaxError::new(error_msg, Span::new(self.file, start, start + 1))
+    }
+
+    /// Advances over a string literal, like `"a\tb"`. Escape sequences
+    /// are skipped over, so that `\"` does not end the string. They are
+    /// processed later, by the parser.
+    fn string(&mut self, start: usize) -> Result<(), SyntaxError> {
+        self.adv(); // Advance over the opening quote
+
+        loop {
+            match self.current_char() {
+                '\0' if self.eof() => return Err(self.unterminated_string(start)),
+                '"' => break,
+                '\\' => {
+                    self.adv();
+                    self.adv();
+                }
+                _ => self.adv(),
+            }
+        }
+
+        self.adv(); // Advance over the closing quote
+        Ok(())
+    }
+
+    /// Returns `true` if the lexer is at the start of a raw string
+    /// literal, which is an `r` followed by zero or more `#`s and a
+    /// double quote.
+    fn at_raw_string(&self) -> bool {
+        let mut chars = self.chars.clone().skip(1).skip_while(|c| *c == '#');
+        chars.next() == Some('"')
+    }
+
+    /// Advances over a raw string literal, like `r"C:\path"`. Raw
+    /// strings do not process escape sequences. If the opening quote is
+    /// preceded by `#`s, the string only ends at a quote followed by
+    /// the same number of `#`s, so `r#"say "hi""#` can contain quotes.
+    fn raw_string(&mut self, start: usize) -> Result<(), SyntaxError> {
+        self.adv(); // Advance over the `r`
+        let hashes = self.take_while(|c| c == '#').range().len();
+        self.adv(); // Advance over the opening quote
+
+        loop {
+            self.take_while(|c| c != '"');
+            if self.eof() {
+                return Err(self.unterminated_string(start));
+            }
+            self.adv();
+
+            // Count the `#`s after the quote, stopping once enough
+            // have been found to close the string.
+            let mut count = 0;
+            while count < hashes && self.current_char() == '#' {
+                self.adv();
+                count += 1;
+            }
+
+            if count == hashes {
+                return Ok(());
             }
         }
     }
@@ -77,20 +218,15 @@ impl<'a> Lexer<'a> {
     /// return [`Err`] if the lexer does not know how to handle a
     /// character.
     #[inline]
-    pub fn next(&mut self) -> Result<Token, SyntaxError> {
+    pub fn next_token(&mut self) -> Result<Token, SyntaxError> {
+        self.skip_trivia()?;
         let start = self.pos;
 
         /// Used for conveniently creating tokens.
         macro_rules! tok {
             ($kind:expr) => {
                 Token {
-                    span: Span::new(start, self.pos),
-                    kind: $kind,
-                }
-            };
-            ($kind:expr, $span:expr) => {
-                Token {
-                    span: $span,
+                    span: Span::new(self.file, start, self.pos),
                     kind: $kind,
                 }
             };
@@ -101,59 +237,55 @@ impl<'a> Lexer<'a> {
                 tok!(Kind::EOF)
             }
 
-            // Skip whitespace characters and get the next token
-            c if c.is_whitespace() => {
-                self.take_while(|c| c.is_whitespace());
-                self.next()?
+            // Matches the opening brace of set literals
+            '#' if self.peek_char() == '{' => {
+                self.adv();
+                self.adv();
+                tok!(Kind::HashBrace)
             }
 
-            // Match integers and floats, which start with a digit.
-            '0'..='9' => {
-                self.take_while(|c| matches!(c, '0'..='9'));
-
-                // If the number is followed by a `.`, treat it as a float
-                if let Some('.') = self.chars.clone().next() {
-                    self.adv();
-                    self.take_while(|c| matches!(c, '0'..='9'));
-
-                    tok!(Kind::Float)
-                } else {
-                    tok!(Kind::Int)
-                }
+            // Matches datum comments, which comment out the expression
+            // that follows them.
+            '#' if self.peek_char() == ';' => {
+                self.adv();
+                self.adv();
+                tok!(Kind::DatumComment)
             }
 
-            // Matches identifiers, which start with an alphabet or an underscore.
-            // Succeding characters may be an alphabet, a number, or an underscore.
-            'a'..='z' | 'A'..='Z' | '_' => {
-                self.take_while(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '_' | '0'..='9'));
-                tok!(Kind::Name)
+            // Matches raw string literals. These have to be checked before
+            // symbols, since they start with an `r`.
+            'r' if self.at_raw_string() => {
+                self.raw_string(start)?;
+                tok!(Kind::String)
             }
 
-            // + and - might denote the sign of a number, so they are parsed seperately
-            // from the other operators.
-            '+' | '-' => {
-                self.adv();
+            // Matches numbers, identifiers and operators. These are all
+            // made of symbol characters, so the whole run of symbol
+            // characters is taken and then classified.
+            c if is_symbol_char(c) => {
+                let span = self.take_while(is_symbol_char);
+                let text = &self.src[span.range()];
+
+                match classify_number(text) {
+                    Some(kind) => tok!(kind),
 
-                // If it is followed by a number, parse it and put the sign as
-                // part of the number token. This will be correcly parsed by
-                // Rust's `.parse()` methods.
-                if let Some('0'..='9') = self.chars.clone().next() {
-                    let kind = self.next()?.kind;
-                    tok!(kind)
-                } else {
-                    tok!(Kind::Operator)
+                    // Anything that starts like a number must be a number,
+                    // so that typos like `1.2.3` are not treated as names.
+                    None if starts_like_number(text) => {
+                        let error_msg = format!("invalid number literal {text:?}");
+                        return Err(SyntaxError::new(error_msg, span));
+                    }
+
+                    None if Op::parse(text).is_some() => tok!(Kind::Operator),
+                    None => tok!(Kind::Name),
                 }
             }
 
             // Matches string literals, which start with a double quote
-            // NOTE: The string token does not include the quotes.
-            // TODO: Add support for escape sequences.
+            // NOTE: The string token includes the quotes.
             '"' => {
-                self.adv();
-
-                let span = self.take_while(|c| !matches!(c, '"'));
-                self.adv(); // Advance over the closing quote
-                tok!(Kind::String, span)
+                self.string(start)?;
+                tok!(Kind::String)
             }
 
             // Matches miscellaneous single-character tokens
@@ -165,11 +297,19 @@ impl<'a> Lexer<'a> {
                 let kind = match c {
                     '(' => Kind::OpenParen,
                     ')' => Kind::CloseParen,
-                    '*' | '/' | '>' | '<' | '=' => Kind::Operator,
+                    '{' => Kind::OpenBrace,
+                    '}' => Kind::CloseBrace,
                     '\'' => Kind::Quote,
+                    '`' => Kind::Quasiquote,
+                    ',' if self.current_char() == '@' => {
+                        self.adv();
+                        Kind::UnquoteSplicing
+                    }
+                    ',' => Kind::Unquote,
                     _ => {
                         let error_msg = format!("did not expect character {c:?}");
-                        return Err(SyntaxError(error_msg));
+                        let span = Span::new(self.file, start, self.pos);
+                        return Err(SyntaxError::new(error_msg, span));
                     }
                 };
 
@@ -180,3 +320,90 @@ impl<'a> Lexer<'a> {
         Ok(token)
     }
 }
+
+/// Returns `true` if a character can be part of a symbol. Symbols
+/// include identifiers like `list->vec`, `empty?` and `*debug*`, as well
+/// as numbers and operators. Any alphanumeric character, including
+/// non-ASCII ones, can be part of a symbol.
+#[inline]
+fn is_symbol_char(c: char) -> bool {
+    c.is_alphanumeric()
+        || matches!(
+            c,
+            '!' | '$' | '%' | '&' | '*' | '/' | ':' | '<' | '=' | '>' | '?' | '^' | '_' | '~' | '+' | '-' | '.'
+        )
+}
+
+/// Returns `true` if a symbol starts with a digit, optionally preceded
+/// by a sign and a decimal point, like `1`, `-2` or `+.5`.
+fn starts_like_number(text: &str) -> bool {
+    let unsigned = text.strip_prefix(['+', '-']).unwrap_or(text);
+    let digits = unsigned.strip_prefix('.').unwrap_or(unsigned);
+    digits.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Counts the digits in a string of digits and underscores. Returns
+/// `None` if the string contains any other character.
+fn count_digits(s: &str, radix: u32) -> Option<usize> {
+    let mut count = 0;
+    for c in s.chars() {
+        match c {
+            '_' => (),
+            c if c.is_digit(radix) => count += 1,
+            _ => return None,
+        }
+    }
+    Some(count)
+}
+
+/// Determines whether a symbol is an int or a float literal. Returns
+/// `None` if it is not a number.
+/// 
+/// Numbers may have a sign, and may use `_` to separate digits. Ints
+/// can be written in hexadecimal (`0xff`), octal (`0o17`) or binary
+/// (`0b1010`). Floats have a decimal point or an exponent, like `1.5`,
+/// `.5`, `1.` or `1e-9`.
+fn classify_number(text: &str) -> Option<Kind> {
+    if !starts_like_number(text) {
+        return None;
+    }
+
+    let unsigned = text.strip_prefix(['+', '-']).unwrap_or(text);
+
+    for (prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+        if let Some(digits) = unsigned.strip_prefix(prefix) {
+            return match count_digits(digits, radix) {
+                Some(n) if n > 0 => Some(Kind::Int),
+                _ => None,
+            };
+        }
+    }
+
+    // Splits off the exponent, making sure that it has digits
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(i) => (&unsigned[..i], Some(&unsigned[i + 1..])),
+        None => (unsigned, None),
+    };
+    if let Some(exponent) = exponent {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        if count_digits(exponent, 10)? == 0 {
+            return None;
+        }
+    }
+
+    let (int_part, frac_part) = match mantissa.split_once('.') {
+        Some((int_part, frac_part)) => (int_part, Some(frac_part)),
+        None => (mantissa, None),
+    };
+
+    count_digits(int_part, 10)?;
+    if let Some(frac_part) = frac_part {
+        count_digits(frac_part, 10)?;
+    }
+
+    if frac_part.is_some() || exponent.is_some() {
+        Some(Kind::Float)
+    } else {
+        Some(Kind::Int)
+    }
+}
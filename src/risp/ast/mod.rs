@@ -3,22 +3,47 @@
 //! [`Token`]s and the [`Parser`] processes these tokens into an
 //! [`AstNode`]. 
 //! 
+//! Every token and node records the [`Span`] of source code that it
+//! came from. The [`SourceMap`] converts spans back into file names,
+//! lines and columns.
+//! 
 //! Invalid syntax may cause a [`SyntaxError`].
 
 
 mod lexer;
 mod parser;
+mod source;
 mod token;
 
 use std::fmt;
+use std::rc::Rc;
 
 pub use lexer::Lexer;
-pub use parser::Parser;
-pub use token::{Token, TokenKind};
+pub use parser::{Parser, MAX_NESTING};
+pub use source::{FileId, SourceMap};
+pub use token::{Span, Token, TokenKind};
+
+/// A node of the abstract syntax tree, along with the span of source
+/// code that it was parsed from.
+#[derive(Clone, Debug)]
+pub struct AstNode {
+    pub kind: NodeKind,
+    pub span: Span,
+}
 
+impl AstNode {
+    /// Creates a new AST node
+    pub fn new(kind: NodeKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+/// The different kinds of [`AstNode`]s
 #[derive(Clone, Debug)]
-pub enum AstNode {
-    Int(i32),
+pub enum NodeKind {
+    Int(i64),
+    /// An int literal that does not fit in 64 bits
+    BigInt(num_bigint::BigInt),
     Float(f64),
 
     Name(String),
@@ -29,12 +54,42 @@ pub enum AstNode {
 
     Expr(Vec<AstNode>),
     List(Vec<AstNode>),
+    /// A map literal. The keys and values are stored alternately.
+    Map(Vec<AstNode>),
+    Set(Vec<AstNode>),
+}
+
+/// An error produced while lexing or parsing source code
+pub struct SyntaxError {
+    pub msg: String,
+    /// The span of source code that caused the error
+    pub span: Span,
+    /// Keeps the source that the span points into from being reused
+    /// while the error exists.
+    pub(crate) files: Vec<Rc<FileId>>,
 }
 
-pub struct SyntaxError(pub String);
+impl SyntaxError {
+    /// Creates a new syntax error
+    pub fn new(msg: impl Into<String>, span: Span) -> Self {
+        Self {
+            msg: msg.into(),
+            span,
+            files: Vec::new(),
+        }
+    }
+}
 
 impl fmt::Debug for SyntaxError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Syntax error: {}", self.0)
+        write!(f, "Syntax error: {}", self.msg)
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SyntaxError: {}", self.msg)
     }
 }
+
+impl std::error::Error for SyntaxError {}
@@ -1,5 +1,13 @@
-use super::{AstNode, Lexer, SyntaxError, Token, TokenKind};
-use crate::risp::shared::Op;
+use super::{AstNode, Lexer, NodeKind, Span, SyntaxError, Token, TokenKind};
+use crate::risp::shared::{grow_stack, Op};
+use num_bigint::BigInt;
+use num_traits::{Num, ToPrimitive};
+
+/// The maximum depth that expressions can be nested to, counting lists,
+/// maps, sets and quotes. Everything that works on code recurses
+/// through it, so this keeps deeply nested code from using up the
+/// native stack.
+pub const MAX_NESTING: usize = 1000;
 
 /// A struct that parses [`Token`]s from a [`Lexer`] into an abstract
 /// syntax tree.
@@ -11,22 +19,50 @@ pub struct Parser<'a> {
     /// The source string to generate the ASTs from. This MUST be the same
     /// as the lexer source string.
     src: &'a str,
+    /// The number of expressions that the current one is nested in.
+    depth: usize,
 }
 
 impl<'a> Parser<'a> {
     /// Creates a new parser
     pub fn new(lexer: &'a mut Lexer<'a>, src: &'a str) -> Result<Self, SyntaxError> {
-        Ok(Self {
-            current_token: lexer.next()?,
+        let mut parser = Self {
+            current_token: lexer.next_token()?,
             src,
             lexer,
-        })
+            depth: 0,
+        };
+
+        parser.skip_datum_comments()?;
+        Ok(parser)
     }
 
     /// Advances the parser to the next token
     #[inline]
     fn advance(&mut self) -> Result<(), SyntaxError> {
-        self.current_token = self.lexer.next()?;
+        self.current_token = self.lexer.next_token()?;
+        self.skip_datum_comments()
+    }
+
+    /// Skips over the expression following a datum comment (`#;`), if
+    /// the parser is on one. Datum comments can be stacked, so
+    /// `#; #; a b` comments out both `a` and `b`.
+    fn skip_datum_comments(&mut self) -> Result<(), SyntaxError> {
+        let mut comments = Vec::new();
+        while self.current_token.kind == TokenKind::DatumComment {
+            comments.push(self.current_token.span);
+            self.current_token = self.lexer.next_token()?;
+        }
+
+        // The innermost comment applies to the first expression
+        while let Some(span) = comments.pop() {
+            if self.current_token.kind == TokenKind::EOF {
+                let error_msg = "expected an expression after datum comment";
+                return Err(SyntaxError::new(error_msg, span));
+            }
+            self.parse_expr()?;
+        }
+
         Ok(())
     }
 
@@ -35,7 +71,7 @@ impl<'a> Parser<'a> {
     fn expect(&mut self, kind: TokenKind) -> Result<(), SyntaxError> {
         if self.current_token.kind != kind {
             let error_msg = format!("expected {kind:?}, found {:?}", self.current_token.kind);
-            return Err(SyntaxError(error_msg));
+            return Err(SyntaxError::new(error_msg, self.current_token.span));
         }
         self.advance()
     }
@@ -46,95 +82,156 @@ impl<'a> Parser<'a> {
         // Tokens store their content using a Span. This line slices
         // the source string to get the characters corresponding
         // to that token.
-        let content = &self.src[self.current_token.span.range()];
+        let span = self.current_token.span;
+        let content = &self.src[span.range()];
         let kind = self.current_token.kind;
 
         self.advance()?;
 
         let node = match &kind {
-            // Parses integers and floats. The `.parse()` method can
-            // handle leading signs as well as edge cases like `1.`
-            // and `.05`.
-            TokenKind::Int => AstNode::Int(content.parse().unwrap()),
-            TokenKind::Float => AstNode::Float(content.parse().unwrap()),
-
-            // Parses a string. The string token does not include the
-            // surrounding quotes, so it can be directly casted into
-            // a String.
-            TokenKind::String => AstNode::Str(content.into()),
-
-            // Parses an operator.
-            TokenKind::Operator => {
-                let op_kind = match &content[..] {
-                    "+" => Op::Plus,
-                    "-" => Op::Minus,
-                    "*" => Op::Star,
-                    "/" => Op::Slash,
-                    "=" => Op::Equal,
-                    ">" => Op::Greater,
-                    "<" => Op::Less,
-
-                    // The lexer will not allow any other character to
-                    // correspond to an Operator token.
-                    _ => unreachable!(),
+            // Parses integers and floats. The lexer has already checked
+            // that the literals are well-formed, but they may still be
+            // out of range.
+            TokenKind::Int => parse_int(content, span)?,
+            TokenKind::Float => NodeKind::Float(parse_float(content, span)?),
+
+            // Parses a string, processing any escape sequences.
+            TokenKind::String => NodeKind::Str(parse_string(content, span)?),
+
+            // Parses an operator. The lexer only produces operator
+            // tokens for valid operators.
+            TokenKind::Operator => NodeKind::Operator(Op::parse(content).unwrap()),
+
+            // Parses an identifier.
+            TokenKind::Name => NodeKind::Name(content.into()),
+
+            // Parses a quote. The span of the quoted node is extended
+            // to include the quote itself. Literals and operators are
+            // the same when quoted, and any other node is wrapped in a
+            // `(quote x)` form.
+            TokenKind::Quote => {
+                let quoted = self.parse_nested(span)?;
+                let quote_span = span;
+                let span = span.to(quoted.span);
+                let kind = match quoted.kind {
+                    NodeKind::Expr(e) => NodeKind::List(e),
+                    NodeKind::Name(e) => NodeKind::Symbol(e),
+                    NodeKind::Map(_) | NodeKind::Set(_) | NodeKind::List(_) | NodeKind::Symbol(_) => {
+                        let head = AstNode::new(NodeKind::Name("quote".into()), quote_span);
+                        NodeKind::Expr(vec![head, quoted])
+                    }
+                    literal => literal,
                 };
-            
-                AstNode::Operator(op_kind)
+                return Ok(AstNode::new(kind, span));
             }
 
-            // Parses an identifier.
-            TokenKind::Name => AstNode::Name(content.into()),
-
-            // Parses a quote
-            TokenKind::Quote => match self.parse_expr()? {
-                AstNode::Expr(e) => AstNode::List(e),
-                AstNode::Name(e) => AstNode::Symbol(e),
-                t => {
-                    let error_msg = format!("{t:?} can not be quoted");
-                    return Err(SyntaxError(error_msg))
-                }
-            },
+            // Reader syntax for quasiquotes, which is expanded into a
+            // form like `(quasiquote x)`.
+            TokenKind::Quasiquote | TokenKind::Unquote | TokenKind::UnquoteSplicing => {
+                let name = match kind {
+                    TokenKind::Quasiquote => "quasiquote",
+                    TokenKind::Unquote => "unquote",
+                    _ => "unquote-splicing",
+                };
+
+                let head = AstNode::new(NodeKind::Name(name.into()), span);
+                let quoted = self.parse_nested(span)?;
+                let span = span.to(quoted.span);
+                return Ok(AstNode::new(NodeKind::Expr(vec![head, quoted]), span));
+            }
 
             // No other tokens are valid atoms.
             t => {
                 let error_msg = format!("unexpected {t:?} while parsing atom");
-                return Err(SyntaxError(error_msg));
+                return Err(SyntaxError::new(error_msg, span));
             }
         };
 
-        return Ok(node);
+        Ok(AstNode::new(node, span))
     }
 
-    /// Parses a list of expressions. A list can contain zero or more
-    /// expressions and is surrounded by a pair of parentheses.
-    fn parse_list(&mut self) -> Result<Vec<AstNode>, SyntaxError> {
-        self.expect(TokenKind::OpenParen)?;
+    /// Parses a sequence of expressions. A sequence can contain zero or
+    /// more expressions, and is surrounded by an opening token (which
+    /// the parser is currently on) and a closing token of kind `close`.
+    /// 
+    /// Returns the expressions, along with a span that covers the
+    /// whole sequence.
+    fn parse_seq(&mut self, close: TokenKind) -> Result<(Vec<AstNode>, Span), SyntaxError> {
+        let start = self.current_token.span;
+        self.advance()?;
 
         let mut elements: Vec<AstNode> = Vec::new();
 
-        // Appends elements to the list while a closing parenthesis
+        // Appends elements to the sequence while the closing token
         // is not encountered. The EOF check prevents infinite loops.
-        while self.current_token.kind != TokenKind::CloseParen
-            && self.current_token.kind != TokenKind::EOF
-        {
-            elements.push(self.parse_expr()?);
+        while self.current_token.kind != close && self.current_token.kind != TokenKind::EOF {
+            elements.push(self.parse_nested(start)?);
         }
 
-        // Verify that a closing parenthesis was encountered, and
-        // not EOF.
-        self.expect(TokenKind::CloseParen)?;
+        // Verify that the closing token was encountered, and not EOF.
+        let end = self.current_token.span;
+        if self.current_token.kind == TokenKind::EOF {
+            let error_msg = match close {
+                TokenKind::CloseParen => "unclosed parenthesis",
+                _ => "unclosed brace",
+            };
+            return Err(SyntaxError::new(error_msg, start));
+        }
+        self.expect(close)?;
 
-        return Ok(elements);
+        Ok((elements, start.to(end)))
+    }
+
+    /// Parses an expression inside a list, map, set or quote, which
+    /// starts at `open`. Returns a [`SyntaxError`] if it is nested more
+    /// than [`MAX_NESTING`] deep.
+    fn parse_nested(&mut self, open: Span) -> Result<AstNode, SyntaxError> {
+        if self.depth >= MAX_NESTING {
+            let error_msg = format!("expressions can not be nested more than {MAX_NESTING} deep");
+            return Err(SyntaxError::new(error_msg, open));
+        }
+
+        self.depth += 1;
+        let node = self.parse_expr();
+        self.depth -= 1;
+        node
     }
 
     /// Parses an expression. An expression may be a list, or an atom
     pub fn parse_expr(&mut self) -> Result<AstNode, SyntaxError> {
+        grow_stack(|| self.parse_node())
+    }
+
+    fn parse_node(&mut self) -> Result<AstNode, SyntaxError> {
         match self.current_token.kind {
             // If the expression begins with a opening parenthesis,
             // then it is a list.
-            TokenKind::OpenParen => Ok(AstNode::Expr(self.parse_list()?)),
+            TokenKind::OpenParen => {
+                let (elements, span) = self.parse_seq(TokenKind::CloseParen)?;
+                Ok(AstNode::new(NodeKind::Expr(elements), span))
+            }
 
-            TokenKind::EOF => Err(SyntaxError("unexpected EOF while parsing atom".into())),
+            // Map literals, like `{"a" 1 "b" 2}`, contain keys and
+            // values alternately.
+            TokenKind::OpenBrace => {
+                let (elements, span) = self.parse_seq(TokenKind::CloseBrace)?;
+                if elements.len() % 2 != 0 {
+                    let error_msg = "map literal must have an even number of elements";
+                    return Err(SyntaxError::new(error_msg, span));
+                }
+                Ok(AstNode::new(NodeKind::Map(elements), span))
+            }
+
+            // Set literals, like `#{1 2 3}`
+            TokenKind::HashBrace => {
+                let (elements, span) = self.parse_seq(TokenKind::CloseBrace)?;
+                Ok(AstNode::new(NodeKind::Set(elements), span))
+            }
+
+            TokenKind::EOF => Err(SyntaxError::new(
+                "unexpected EOF while parsing atom",
+                self.current_token.span,
+            )),
 
             // Anything else is parsed as an atom.
             _ => self.parse_atom(),
@@ -145,10 +242,144 @@ impl<'a> Parser<'a> {
     pub fn parse_exprs(&mut self) -> Result<Vec<AstNode>, SyntaxError> {
         let mut exprs = Vec::new();
 
-        while !self.lexer.eof() {
+        while self.current_token.kind != TokenKind::EOF {
             exprs.push(self.parse_expr()?);
         }
 
         Ok(exprs)
     }
 }
+
+/// Converts a string literal, including its quotes, into the string
+/// that it represents.
+/// 
+/// Raw strings like `r#"C:\path"#` are returned as-is. In other strings,
+/// the following escape sequences are processed:
+/// - `\n`, `\t`, `\r` and `\0`
+/// - `\\`, `\"` and `\'`
+/// - `\u{...}`, for a unicode code point written in hexadecimal
+/// - a `\` at the end of a line, which skips the newline, or CRLF,
+///   and any leading whitespace on the next line
+fn parse_string(literal: &str, span: Span) -> Result<String, SyntaxError> {
+    if let Some(raw) = literal.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        return Ok(raw[hashes + 1..raw.len() - hashes - 1].into());
+    }
+
+    let inner = &literal[1..literal.len() - 1];
+    let mut chars = inner.char_indices().peekable();
+    let mut string = String::with_capacity(inner.len());
+
+    while let Some((i, c)) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+
+        // The escape sequence starts after the opening quote, and
+        // covers the backslash and the character after it.
+        let start = span.start + 1 + i;
+        let escape_error = |msg: &str, len: usize| {
+            SyntaxError::new(msg, Span::new(span.file, start, start + len))
+        };
+
+        let escaped = match chars.next() {
+            Some((_, 'n')) => '\n',
+            Some((_, 't')) => '\t',
+            Some((_, 'r')) => '\r',
+            Some((_, '0')) => '\0',
+            Some((_, '\\')) => '\\',
+            Some((_, '"')) => '"',
+            Some((_, '\'')) => '\'',
+
+            // Line continuations skip the newline, which may be a CRLF,
+            // and the indentation of the next line.
+            Some((_, '\n')) => {
+                while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+                continue;
+            }
+            Some((_, '\r')) if chars.peek().is_some_and(|(_, c)| *c == '\n') => {
+                while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+                continue;
+            }
+
+            Some((_, 'u')) => {
+                if chars.next_if(|(_, c)| *c == '{').is_none() {
+                    return Err(escape_error("expected `{` after \\u", 2));
+                }
+
+                let mut digits = String::new();
+                while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_hexdigit()) {
+                    digits.push(c);
+                }
+
+                let len = 3 + digits.len();
+                if chars.next_if(|(_, c)| *c == '}').is_none() {
+                    return Err(escape_error("malformed unicode escape", len));
+                }
+
+                let code = u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32);
+                match code {
+                    Some(c) => c,
+                    None => return Err(escape_error("invalid unicode escape", len + 1)),
+                }
+            }
+
+            Some((_, c)) => {
+                let error_msg = format!("unknown escape sequence \\{c}");
+                return Err(escape_error(&error_msg, 1 + c.len_utf8()));
+            }
+
+            // The lexer does not allow a string to end with a backslash
+            None => unreachable!(),
+        };
+
+        string.push(escaped);
+    }
+
+    Ok(string)
+}
+
+/// Parses an int literal, which may have a sign, a radix prefix like
+/// `0x`, and underscores between digits. Literals that do not fit in
+/// 64 bits become big ints.
+fn parse_int(literal: &str, span: Span) -> Result<NodeKind, SyntaxError> {
+    let digits: String = literal.chars().filter(|c| *c != '_').collect();
+    let (negative, unsigned) = match digits.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, digits.strip_prefix('+').unwrap_or(&digits)),
+    };
+
+    let (radix, unsigned) = match unsigned.get(..2) {
+        Some("0x") => (16, &unsigned[2..]),
+        Some("0o") => (8, &unsigned[2..]),
+        Some("0b") => (2, &unsigned[2..]),
+        _ => (10, unsigned),
+    };
+
+    // The sign is added back before parsing, so that the most negative
+    // int can be written.
+    let signed = if negative { format!("-{unsigned}") } else { unsigned.into() };
+    let int = BigInt::from_str_radix(&signed, radix).map_err(|_| {
+        let error_msg = format!("invalid int literal {literal}");
+        SyntaxError::new(error_msg, span)
+    })?;
+
+    match int.to_i64() {
+        Some(int) => Ok(NodeKind::Int(int)),
+        None => Ok(NodeKind::BigInt(int)),
+    }
+}
+
+/// Parses a float literal, which may have underscores between digits.
+fn parse_float(literal: &str, span: Span) -> Result<f64, SyntaxError> {
+    let digits: String = literal.chars().filter(|c| *c != '_').collect();
+
+    match digits.parse::<f64>() {
+        Ok(f) if f.is_finite() => Ok(f),
+        _ => {
+            let error_msg = format!("float literal {literal} is out of range");
+            Err(SyntaxError::new(error_msg, span))
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use super::Span;
+
+/// An index into a [`SourceMap`], identifying a source file.
+pub type FileId = usize;
+
+/// A named piece of source code, such as a script or a line typed
+/// into the REPL.
+pub struct SourceFile {
+    /// The name of the file, used when reporting errors.
+    pub name: String,
+    /// The contents of the file.
+    pub src: String,
+    /// The byte offsets at which each line starts.
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    /// Creates a new source file
+    pub fn new(name: &str, src: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self {
+            name: name.into(),
+            src: src.into(),
+            line_starts,
+        }
+    }
+
+    /// Converts a byte offset into a 1-based line and column. The
+    /// column is counted in characters, not bytes.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+
+        let start = self.line_starts[line];
+        let end = offset.min(self.src.len());
+        let col = self.src[start..end].chars().count();
+
+        (line + 1, col + 1)
+    }
+
+    /// Returns the byte offset at which a 1-based line starts.
+    pub fn line_start(&self, line: usize) -> usize {
+        self.line_starts[line - 1]
+    }
+
+    /// Returns the text of a 1-based line, without the trailing newline.
+    pub fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = match self.line_starts.get(line) {
+            Some(next) => next - 1,
+            None => self.src.len(),
+        };
+
+        self.src[start..end].trim_end_matches('\r')
+    }
+}
+
+/// Stores all of the source code that has been loaded, so that spans
+/// can be converted back into file names, lines and columns.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    /// Creates a new, empty source map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file to the source map, returning its [`FileId`]
+    pub fn add(&mut self, name: &str, src: &str) -> FileId {
+        self.files.push(SourceFile::new(name, src));
+        self.files.len() - 1
+    }
+
+    /// Replaces the contents of a file, keeping its name. Spans that
+    /// refer to the old contents can no longer be converted correctly.
+    pub fn replace(&mut self, file: FileId, src: &str) {
+        let name = std::mem::take(&mut self.files[file].name);
+        self.files[file] = SourceFile::new(&name, src);
+    }
+
+    /// Retrieves a file from the source map
+    pub fn get(&self, file: FileId) -> Option<&SourceFile> {
+        self.files.get(file)
+    }
+
+    /// Formats the start of a span as `file:line:column`. Returns `None`
+    /// if the span's file is not in the source map.
+    pub fn location(&self, span: Span) -> Option<String> {
+        let file = self.get(span.file)?;
+        let (line, col) = file.line_col(span.start);
+
+        Some(format!("{}:{line}:{col}", file.name))
+    }
+}
@@ -1,6 +1,10 @@
+use super::FileId;
+
 /// A simple span of lines, or a start and end position
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct Span {
+    /// The file that the span references
+    pub file: FileId,
     /// The start location that the span references in the source
     pub start: usize,
     /// The end location that the span references in the source
@@ -9,8 +13,13 @@ pub struct Span {
 
 impl Span {
     /// Creates a new span
-    pub fn new(start: usize, end: usize) -> Self {
-        Self { start, end }
+    pub fn new(file: FileId, start: usize, end: usize) -> Self {
+        Self { file, start, end }
+    }
+
+    /// Creates a span that covers both `self` and `other`
+    pub fn to(&self, other: Span) -> Self {
+        Self::new(self.file, self.start, other.end)
     }
 
     /// Converts the span to a [`Range<usize>`](core::ops::Range)
@@ -29,17 +38,32 @@ pub enum TokenKind {
     Int,
     /// A floating point number
     Float,
-    /// A string, without the surrounding quotes
+    /// A string or raw string, including the surrounding quotes
     String,
     /// An opening parenthesis
     OpenParen,
     /// A closing parenthesis
     CloseParen,
+    /// An opening brace, which starts a map literal
+    OpenBrace,
+    /// A `#{`, which starts a set literal
+    HashBrace,
+    /// A closing brace
+    CloseBrace,
     /// A quote
     Quote,
+    /// A backquote, which starts a quasiquote
+    Quasiquote,
+    /// A comma, which unquotes an expression inside a quasiquote
+    Unquote,
+    /// A `,@`, which splices a list into a quasiquote
+    UnquoteSplicing,
     /// An operator
     Operator,
+    /// A datum comment (`#;`), which comments out the next expression
+    DatumComment,
     /// Represents the end of the source string
+    #[allow(clippy::upper_case_acronyms)]
     EOF,
 }
 
@@ -0,0 +1,184 @@
+//! This module renders [`SyntaxError`]s and [`RuntimeError`]s for
+//! humans. A rendered [`Diagnostic`] shows the kind of error, its
+//! location, the offending line of source code with the span
+//! underlined, and an optional help message:
+//! 
+//! ```text
+//! error[NameError]: pritnln is not defined
+//!  --> scripts/example.risp:1:2
+//!   |
+//! 1 | (pritnln "Hello World")
+//!   |  ^^^^^^^
+//!   = help: a symbol with a similar name exists: `println`
+//! ```
+//! 
+//! Runtime errors that occur inside function calls are preceded by a
+//! traceback, with the most recent call last.
+
+use crate::risp::{RuntimeError, SourceMap, Span, StackFrame, SyntaxError};
+
+/// ANSI escape codes used when colour is enabled
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// An error that can be rendered along with the source code that
+/// produced it.
+pub struct Diagnostic {
+    /// The kind of error, such as `SyntaxError` or `NameError`
+    pub kind: String,
+    pub msg: String,
+    /// The span of source code that caused the error, if it is known
+    pub span: Option<Span>,
+    /// An optional note on how the error might be fixed
+    pub help: Option<String>,
+    /// The call stack when the error occured, with the outermost call
+    /// first
+    pub backtrace: Vec<StackFrame>,
+}
+
+impl From<&SyntaxError> for Diagnostic {
+    fn from(err: &SyntaxError) -> Self {
+        Self {
+            kind: "SyntaxError".into(),
+            msg: err.msg.clone(),
+            span: Some(err.span),
+            help: None,
+            backtrace: Vec::new(),
+        }
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(err: &RuntimeError) -> Self {
+        Self {
+            kind: format!("{:?}", err.kind),
+            msg: err.msg.clone(),
+            span: err.span,
+            help: err.help.clone(),
+            backtrace: err.backtrace.clone(),
+        }
+    }
+}
+
+impl Diagnostic {
+    /// Renders the diagnostic. The source code is looked up in
+    /// `sources`, and ANSI colours are used if `color` is `true`.
+    pub fn render(&self, sources: &SourceMap, color: bool) -> String {
+        // Returns the escape code if colour is enabled
+        let paint = |code: &'static str| if color { code } else { "" };
+        let (red, blue, bold, reset) = (paint(RED), paint(BLUE), paint(BOLD), paint(RESET));
+
+        let mut out = self.render_backtrace(sources);
+        out += &format!("{red}error[{}]{reset}{bold}: {}{reset}\n", self.kind, self.msg);
+
+        let snippet = self.span.and_then(|span| {
+            let file = sources.get(span.file)?;
+            Some((span, file, sources.location(span)?))
+        });
+
+        // Errors without a known location only show their message
+        let (span, file, location) = match snippet {
+            Some(snippet) => snippet,
+            None => {
+                if let Some(help) = &self.help {
+                    out += &format!("  {blue}={reset} {bold}help{reset}: {help}\n");
+                }
+                return out;
+            }
+        };
+
+        let (line, col) = file.line_col(span.start);
+        let text = file.line_text(line);
+        let gutter = " ".repeat(line.to_string().len());
+
+        // The underline stops at the end of the line, since only the
+        // first line of a span is shown.
+        let line_start = file.line_start(line);
+        let end = span.end.min(line_start + text.len()).max(span.start + 1);
+        let width = file.src.get(span.start..end).map_or(1, |s| s.chars().count().max(1));
+
+        // Tabs are kept in the padding so that the caret lines up
+        // with the source line.
+        let padding: String = text
+            .chars()
+            .take(col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        out += &format!("{gutter}{blue}-->{reset} {location}\n");
+        out += &format!("{gutter} {blue}|{reset}\n");
+        out += &format!("{blue}{line} |{reset} {text}\n");
+        out += &format!("{gutter} {blue}|{reset} {padding}{red}{}{reset}\n", "^".repeat(width));
+
+        if let Some(help) = &self.help {
+            out += &format!("{gutter} {blue}={reset} {bold}help{reset}: {help}\n");
+        }
+
+        out
+    }
+
+    /// Renders the backtrace, with the most recent call last. Cycles of
+    /// frames that repeat, which are produced by recursion, are only
+    /// shown once.
+    fn render_backtrace(&self, sources: &SourceMap) -> String {
+        if self.backtrace.is_empty() {
+            return String::new();
+        }
+
+        let frames = &self.backtrace;
+        let mut out = String::from("Traceback (most recent call last):\n");
+        let mut i = 0;
+
+        while i < frames.len() {
+            let (period, repeats) = find_cycle(&frames[i..]);
+
+            for frame in &frames[i..i + period] {
+                let location = sources
+                    .location(frame.span)
+                    .unwrap_or_else(|| "<unknown>".into());
+                out += &format!("  {location}, in {}\n", frame.name);
+
+                if let Some(file) = sources.get(frame.span.file) {
+                    let (line, _) = file.line_col(frame.span.start);
+                    out += &format!("    {}\n", file.line_text(line).trim());
+                }
+            }
+
+            match (period, repeats) {
+                (_, 0) => (),
+                (1, _) => out += &format!("  [Previous frame repeated {repeats} more times]\n"),
+                _ => out += &format!("  [Previous {period} frames repeated {repeats} more times]\n"),
+            }
+
+            i += period * (repeats + 1);
+        }
+
+        out
+    }
+}
+
+/// The longest cycle of frames that is collapsed in backtraces.
+/// Recursion through macros like `if` produces cycles longer than one
+/// frame.
+const MAX_CYCLE: usize = 4;
+
+/// Finds the shortest cycle at the start of `frames` that repeats
+/// immediately after itself. Returns the length of the cycle and the
+/// number of times it repeats, or `(1, 0)` if there is no cycle.
+fn find_cycle(frames: &[StackFrame]) -> (usize, usize) {
+    for period in 1..=MAX_CYCLE.min(frames.len() / 2) {
+        let block = &frames[..period];
+        let repeats = frames[period..]
+            .chunks_exact(period)
+            .take_while(|chunk| *chunk == block)
+            .count();
+
+        if repeats > 0 {
+            return (period, repeats);
+        }
+    }
+
+    (1, 0)
+}
@@ -0,0 +1,55 @@
+//! The error type returned by the convenience methods of the
+//! [`Interpreter`](crate::risp::Interpreter), which covers every way
+//! that running source code can fail.
+
+use std::{error, fmt};
+
+use crate::risp::{Diagnostic, RuntimeError, SyntaxError};
+
+/// An error produced while parsing or running risp code
+#[derive(Debug)]
+pub enum Error {
+    /// The source code could not be parsed
+    Syntax(SyntaxError),
+    /// The code raised an error while it was running
+    Runtime(RuntimeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Syntax(err) => err.fmt(f),
+            Error::Runtime(err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Syntax(err) => Some(err),
+            Error::Runtime(err) => Some(err),
+        }
+    }
+}
+
+impl From<SyntaxError> for Error {
+    fn from(err: SyntaxError) -> Self {
+        Error::Syntax(err)
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(err: RuntimeError) -> Self {
+        Error::Runtime(err)
+    }
+}
+
+impl From<&Error> for Diagnostic {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::Syntax(err) => Diagnostic::from(err),
+            Error::Runtime(err) => Diagnostic::from(err),
+        }
+    }
+}
@@ -1,10 +1,124 @@
-#[derive(Clone, Copy, Debug, PartialEq)]
+use std::cmp::Ordering;
+
+/// The stack that must be left for a recursive function to keep running
+/// on the current stack
+const RED_ZONE: usize = 256 * 1024;
+
+/// The size of the stacks that are allocated when one runs out
+const STACK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Runs a function which recurses into code or values, like the parser
+/// or the tree-walking interpreter. If the native stack is nearly used
+/// up, the function is run on a new stack allocated on the heap, so
+/// deeply nested code can not overflow the stack of the thread that
+/// the interpreter is used on.
+pub(crate) fn grow_stack<T>(func: impl FnOnce() -> T) -> T {
+    stacker::maybe_grow(RED_ZONE, STACK_SIZE, func)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Op {
     Plus,
     Minus,
     Star,
     Slash,
+    /// Floored modulo, which has the sign of the divisor
+    Mod,
+    /// Truncated remainder, which has the sign of the dividend
+    Rem,
+    /// Truncated division
+    Quot,
+    Pow,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
     Equal,
+    NotEqual,
     Greater,
+    GreaterEqual,
     Less,
+    LessEqual,
+}
+
+impl Op {
+    /// Every operator, used for looking operators up by their symbol
+    const ALL: [Op; 19] = [
+        Op::Plus,
+        Op::Minus,
+        Op::Star,
+        Op::Slash,
+        Op::Mod,
+        Op::Rem,
+        Op::Quot,
+        Op::Pow,
+        Op::BitAnd,
+        Op::BitOr,
+        Op::BitXor,
+        Op::Shl,
+        Op::Shr,
+        Op::Equal,
+        Op::NotEqual,
+        Op::Greater,
+        Op::GreaterEqual,
+        Op::Less,
+        Op::LessEqual,
+    ];
+
+    /// Returns the symbol that the operator is written as
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Op::Plus => "+",
+            Op::Minus => "-",
+            Op::Star => "*",
+            Op::Slash => "/",
+            Op::Mod => "%",
+            Op::Rem => "rem",
+            Op::Quot => "quot",
+            Op::Pow => "**",
+            Op::BitAnd => "bit-and",
+            Op::BitOr => "bit-or",
+            Op::BitXor => "bit-xor",
+            Op::Shl => "bit-shl",
+            Op::Shr => "bit-shr",
+            Op::Equal => "=",
+            Op::NotEqual => "!=",
+            Op::Greater => ">",
+            Op::GreaterEqual => ">=",
+            Op::Less => "<",
+            Op::LessEqual => "<=",
+        }
+    }
+
+    /// Returns another name that the operator can be written as
+    pub fn alias(&self) -> Option<&'static str> {
+        match self {
+            Op::Mod => Some("mod"),
+            Op::Pow => Some("pow"),
+            _ => None,
+        }
+    }
+
+    /// Returns the operator written as `symbol`, if there is one
+    pub fn parse(symbol: &str) -> Option<Op> {
+        Self::ALL
+            .into_iter()
+            .find(|op| op.as_str() == symbol || op.alias() == Some(symbol))
+    }
+
+    /// For comparison operators, returns a function which checks whether
+    /// an [`Ordering`] satisfies the comparison.
+    pub fn comparison(&self) -> Option<fn(Ordering) -> bool> {
+        let check: fn(Ordering) -> bool = match self {
+            Op::Equal => Ordering::is_eq,
+            Op::NotEqual => Ordering::is_ne,
+            Op::Greater => Ordering::is_gt,
+            Op::GreaterEqual => Ordering::is_ge,
+            Op::Less => Ordering::is_lt,
+            Op::LessEqual => Ordering::is_le,
+            _ => return None,
+        };
+        Some(check)
+    }
 }
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::risp::{ErrorKind, Interpreter, Range, RuntimeError, Value};
+
+macro_rules! err {
+    ($kind:ident, $msg:expr) => {
+        Err(RuntimeError::new(ErrorKind::$kind, $msg))
+    };
+}
+
+/// Checks that a function received a number of arguments within
+/// `min..=max`.
+fn check_arity(name: &str, args: &[Value], min: usize, max: usize) -> Result<(), RuntimeError> {
+    if args.len() < min || args.len() > max {
+        return Err(RuntimeError::arity(name, min, max, args.len()));
+    }
+    Ok(())
+}
+
+/// Creates a [`RuntimeError`] for an argument of the wrong type
+fn type_error(name: &str, expected: &str, found: &Value) -> RuntimeError {
+    let msg = format!("{name} expected {expected}, found {}", found.type_name());
+    RuntimeError::new(ErrorKind::TypeError, msg)
+}
+
+/// Gets the value associated with a key in a map, like `(get m "a")`.
+/// Also gets elements of lists by index, and checks membership in sets.
+/// Returns the optional third argument, or null, if the key is absent.
+pub fn get(_: &mut Interpreter, args: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    check_arity("get", &args, 2, 3)?;
+    let default = args.get(2).cloned().unwrap_or(Value::Null);
+
+    let value = match (&args[0], &args[1]) {
+        (Value::Map(m), key) => m.get(key).cloned(),
+        (Value::Set(s), key) => s.get(key).cloned(),
+        (Value::List(l), Value::Int(i)) => usize::try_from(*i).ok().and_then(|i| l.get(i)).cloned(),
+        (Value::List(_), index) => return Err(type_error("get", "an int index", index)),
+        (other, _) => return Err(type_error("get", "a map, set or list", other)),
+    };
+
+    Ok(vec![value.unwrap_or(default)])
+}
+
+/// Returns a copy of a map with keys associated to new values, like
+/// `(assoc m "a" 1 "b" 2)`.
+pub fn assoc(_: &mut Interpreter, args: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    check_arity("assoc", &args, 1, usize::MAX)?;
+    if args.len() % 2 != 1 {
+        return err!(ValueError, "assoc expected a value for every key");
+    }
+
+    let mut args = args.into_iter();
+    let mut map = match args.next() {
+        Some(Value::Map(m)) => m,
+        Some(other) => return Err(type_error("assoc", "a map", &other)),
+        None => unreachable!(),
+    };
+
+    while let (Some(key), Some(value)) = (args.next(), args.next()) {
+        map.insert(key, value);
+    }
+
+    Ok(vec![Value::Map(map)])
+}
+
+/// Returns a copy of a map without some keys, like `(dissoc m "a" "b")`.
+/// The order of the remaining keys is preserved.
+pub fn dissoc(_: &mut Interpreter, args: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    check_arity("dissoc", &args, 1, usize::MAX)?;
+
+    let mut args = args.into_iter();
+    let mut map = match args.next() {
+        Some(Value::Map(m)) => m,
+        Some(other) => return Err(type_error("dissoc", "a map", &other)),
+        None => unreachable!(),
+    };
+
+    for key in args {
+        map.shift_remove(&key);
+    }
+
+    Ok(vec![Value::Map(map)])
+}
+
+/// Returns the keys of a map as a list, in insertion order
+pub fn keys(_: &mut Interpreter, args: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    check_arity("keys", &args, 1, 1)?;
+
+    match &args[0] {
+        Value::Map(m) => Ok(vec![Value::List(m.keys().cloned().collect())]),
+        other => Err(type_error("keys", "a map", other)),
+    }
+}
+
+/// Returns the values of a map as a list, in insertion order
+pub fn vals(_: &mut Interpreter, args: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    check_arity("vals", &args, 1, 1)?;
+
+    match &args[0] {
+        Value::Map(m) => Ok(vec![Value::List(m.values().cloned().collect())]),
+        other => Err(type_error("vals", "a map", other)),
+    }
+}
+
+/// Checks whether a map contains a key, or a set or list contains a
+/// value, like `(contains? #{1 2} 1)`.
+pub fn contains(_: &mut Interpreter, args: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    check_arity("contains?", &args, 2, 2)?;
+
+    let found = match (&args[0], &args[1]) {
+        (Value::Map(m), key) => m.contains_key(key),
+        (Value::Set(s), value) => s.contains(value),
+        (Value::List(l), value) => l.contains(value),
+        (other, _) => return Err(type_error("contains?", "a map, set or list", other)),
+    };
+
+    Ok(vec![Value::Bool(found)])
+}
+
+/// Merges maps from left to right, like `(merge a b)`. If a key appears
+/// in more than one map, the value from the rightmost map is used.
+pub fn merge(_: &mut Interpreter, args: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    let mut merged = IndexMap::new();
+
+    for arg in args {
+        match arg {
+            Value::Map(m) => merged.extend(m),
+            other => return Err(type_error("merge", "a map", &other)),
+        }
+    }
+
+    Ok(vec![Value::Map(merged)])
+}
+
+/// Returns the union of sets, like `(union #{1 2} #{2 3})`
+pub fn union(_: &mut Interpreter, args: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    let mut result = IndexSet::new();
+
+    for arg in args {
+        match arg {
+            Value::Set(s) => result.extend(s),
+            other => return Err(type_error("union", "a set", &other)),
+        }
+    }
+
+    Ok(vec![Value::Set(result)])
+}
+
+/// Returns the intersection of sets, like `(intersection #{1 2} #{2 3})`.
+/// The elements keep the order they had in the first set.
+pub fn intersection(_: &mut Interpreter, args: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    check_arity("intersection", &args, 1, usize::MAX)?;
+
+    let mut sets = Vec::new();
+    for arg in args {
+        match arg {
+            Value::Set(s) => sets.push(s),
+            other => return Err(type_error("intersection", "a set", &other)),
+        }
+    }
+
+    let mut sets = sets.into_iter();
+    let mut result = sets.next().unwrap();
+    for set in sets {
+        result.retain(|value| set.contains(value));
+    }
+
+    Ok(vec![Value::Set(result)])
+}
+
+/// Returns the elements of a list, set, or the keys of a map, sorted
+/// using the ordering of values, like `(sort (list 3 1 2))`.
+pub fn sort(_: &mut Interpreter, args: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    check_arity("sort", &args, 1, 1)?;
+
+    let mut items: Vec<Value> = match args.into_iter().next().unwrap() {
+        Value::List(l) => l,
+        Value::Set(s) => s.into_iter().collect(),
+        Value::Map(m) => m.into_keys().collect(),
+        other => return Err(type_error("sort", "a list, set or map", &other)),
+    };
+    items.sort();
+
+    Ok(vec![Value::List(items)])
+}
+
+/// Creates a range of ints, like `(range 10)`, `(range 1 10)` or
+/// `(range 10 0 -2)`. Ranges start at 0 by default, and count in steps
+/// of 1. The ints are not stored, so ranges can be very long.
+pub fn range(_: &mut Interpreter, args: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    check_arity("range", &args, 1, 3)?;
+
+    let mut bounds = Vec::new();
+    for arg in &args {
+        match arg {
+            Value::Int(n) => bounds.push(*n),
+            other => return Err(type_error("range", "an int", other)),
+        }
+    }
+
+    let (start, end, step) = match bounds[..] {
+        [end] => (0, end, 1),
+        [start, end] => (start, end, 1),
+        [start, end, step] => (start, end, step),
+        _ => unreachable!(),
+    };
+    if step == 0 {
+        return err!(ValueError, "range step must not be zero");
+    }
+
+    Ok(vec![Value::Range(Range { start, end, step })])
+}
+
+/// Calls a function with each element of a list, set, string, map or
+/// range, like `(each println (list 1 2 3))`. Map entries are passed as
+/// `[key value]` lists. Every call takes a step, even if the function
+/// is a builtin.
+pub fn each(inter: &mut Interpreter, args: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    check_arity("each", &args, 2, 2)?;
+
+    let values = match args[1].iter() {
+        Some(values) => values,
+        None => return Err(type_error("each", "a list, set, str, map or range", &args[1])),
+    };
+    for value in values {
+        inter.tick()?;
+        inter.call_value(&args[0], vec![value])?;
+    }
+
+    Ok(Vec::new())
+}
+
+/// Returns the symbols defined by this module, to be loaded into the
+/// interpreter's global scope.
+pub fn symbols() -> HashMap<String, Value> {
+    let mut h = HashMap::new();
+    h.insert("get".into(), Value::RustFn(Rc::new(get)));
+    h.insert("assoc".into(), Value::RustFn(Rc::new(assoc)));
+    h.insert("dissoc".into(), Value::RustFn(Rc::new(dissoc)));
+    h.insert("keys".into(), Value::RustFn(Rc::new(keys)));
+    h.insert("vals".into(), Value::RustFn(Rc::new(vals)));
+    h.insert("contains?".into(), Value::RustFn(Rc::new(contains)));
+    h.insert("merge".into(), Value::RustFn(Rc::new(merge)));
+    h.insert("union".into(), Value::RustFn(Rc::new(union)));
+    h.insert("intersection".into(), Value::RustFn(Rc::new(intersection)));
+    h.insert("sort".into(), Value::RustFn(Rc::new(sort)));
+    h.insert("range".into(), Value::RustFn(Rc::new(range)));
+    h.insert("each".into(), Value::RustFn(Rc::new(each)));
+    h
+}
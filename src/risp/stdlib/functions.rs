@@ -1,49 +1,186 @@
 use std::collections::HashMap;
-use std::{io, io::prelude::*};
+use std::rc::Rc;
 
-use crate::risp::{RuntimeError, Value, Interpreter};
+use crate::risp::vm::to_value;
+use crate::risp::{ErrorKind, RuntimeError, Value, Interpreter};
 
-/// Prints values to STDOUT, without a trailing newline
-pub fn print(_: &mut Interpreter, _in: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
-    let mut iter = _in.iter();
-
-    match iter.next() {
-        Some(v) => print!("{}", v.display()),
-        None => (),
-    }
-
-    for el in iter {
-        print!(" {}", el.display());
-    }
-
-    io::stdout().flush().unwrap();
+/// Writes values to the interpreter's output, separated by spaces and
+/// followed by `end`
+fn write_values(inter: &mut Interpreter, values: &[Value], end: &str) -> Result<(), RuntimeError> {
+    let values: Vec<String> = values.iter().map(Value::display).collect();
+    let output = inter.output();
+    write!(output, "{}{end}", values.join(" "))?;
+    output.flush()?;
+    Ok(())
+}
 
+/// Prints values to the interpreter's output, which is STDOUT by
+/// default, without a trailing newline
+pub fn print(inter: &mut Interpreter, _in: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    write_values(inter, &_in, "")?;
     Ok(Vec::new())
 }
 
-/// Prints values to STDOUT, followed by a newline
+/// Prints values to the interpreter's output, followed by a newline
 pub fn println(inter: &mut Interpreter, _in: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
-    print(inter, _in)?;
-    print!("\n");
-
+    write_values(inter, &_in, "\n")?;
     Ok(Vec::new())
 }
 
+/// Prints values as a prompt, then reads a line from the interpreter's
+/// input, which is STDIN by default. The line is returned without its
+/// trailing newline.
 pub fn input(inter: &mut Interpreter, _in: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
-    print(inter, _in)?;
+    write_values(inter, &_in, "")?;
 
     let mut buffer = String::new();
-    io::stdin().read_line(&mut buffer).unwrap();
+    inter.read_line(&mut buffer)?;
 
     Ok(vec![Value::Str(buffer.trim_end().to_owned())])
 }
 
-lazy_static! {
-    pub static ref SYMBOLS: HashMap<String, Value> = {
-        let mut h = HashMap::new();
-        h.insert("println".into(), Value::RustFn(println));
-        h.insert("print".into(), Value::RustFn(print));
-        h.insert("input".into(), Value::RustFn(input));
-        h
+/// Returns the argument that `name` was called with, which must be its
+/// only argument.
+fn single_arg(name: &str, _in: Vec<Value>) -> Result<Value, RuntimeError> {
+    match <[Value; 1]>::try_from(_in) {
+        Ok([arg]) => Ok(arg),
+        Err(args) => Err(RuntimeError::arity(name, 1, 1, args.len())),
+    }
+}
+
+/// Raises any value as an error, like `(throw {"code" 404})`. The
+/// value is bound unchanged by the `catch` clause of a `try`.
+pub fn throw(_: &mut Interpreter, _in: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    let value = single_arg("throw", _in)?;
+
+    let mut error = RuntimeError::new(ErrorKind::Thrown, value.display());
+    error.value = Some(Box::new(value));
+    Err(error)
+}
+
+/// Raises a `UserError`, like `(error "n must be positive:" n)`. The
+/// message is made of the arguments, separated by spaces.
+pub fn error(_: &mut Interpreter, _in: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    let msg: Vec<String> = _in.iter().map(Value::display).collect();
+    Err(RuntimeError::new(ErrorKind::UserError, msg.join(" ")))
+}
+
+/// Creates a new symbol, which is different from every other symbol
+/// created by `gensym`. Macros use these as names that can not clash
+/// with names in the code that they are given. The symbol's name
+/// starts with the optional prefix, like `(gensym "tmp")`.
+pub fn gensym(inter: &mut Interpreter, _in: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    let prefix = match _in.as_slice() {
+        [] => "G",
+        [Value::Str(prefix)] => prefix,
+        _ => {
+            let msg = "gensym expected an optional str prefix";
+            return Err(RuntimeError::new(ErrorKind::TypeError, msg));
+        }
+    };
+
+    inter.gensyms += 1;
+    Ok(vec![Value::Symbol(format!("{prefix}__{}", inter.gensyms))])
+}
+
+/// Expands a form once if it is a call to a macro defined in risp.
+/// Returns `None` if it is not.
+fn expand_once(inter: &mut Interpreter, form: &Value) -> Result<Option<Value>, RuntimeError> {
+    let (name, args) = match form {
+        Value::List(items) => match items.split_first() {
+            Some((Value::Symbol(name), args)) => (name, args),
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
     };
+
+    match inter.get_name(name) {
+        Ok(Value::Macro(mac)) => inter.call_lambda(&mac, args.to_vec()).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Expands a call to a macro once, like
+/// ``(macroexpand-1 `(unless done (println "working")))``. Forms which are
+/// not macro calls are returned unchanged.
+pub fn macroexpand_1(inter: &mut Interpreter, _in: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    let form = single_arg("macroexpand-1", _in)?;
+    Ok(vec![expand_once(inter, &form)?.unwrap_or(form)])
+}
+
+/// Expands a call to a macro repeatedly, until it is no longer a macro
+/// call. Only the outermost form is expanded.
+pub fn macroexpand(inter: &mut Interpreter, _in: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    let mut form = single_arg("macroexpand", _in)?;
+    while let Some(expansion) = expand_once(inter, &form)? {
+        form = expansion;
+    }
+
+    Ok(vec![form])
+}
+
+/// Parses a string of code into data, like `(read-string "(+ 1 2)")`.
+/// The string must contain exactly one expression.
+pub fn read_string(inter: &mut Interpreter, _in: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    let src = match _in.as_slice() {
+        [Value::Str(src)] => src,
+        _ => return Err(RuntimeError::new(ErrorKind::TypeError, "read-string expected a str")),
+    };
+
+    // Errors in the parsed code are reported against the string, which
+    // the error holds on to. The nodes are turned into data without
+    // spans, so otherwise the next call can reuse the string's file.
+    let (file, nodes) = inter.load_spare("<string>", src);
+    let nodes = nodes.map_err(|err| {
+        let mut error = RuntimeError::new(ErrorKind::SyntaxError, err.msg);
+        error.span = Some(err.span);
+        error.files.push(file);
+        error
+    })?;
+
+    match nodes.as_slice() {
+        [node] => Ok(vec![to_value(node)]),
+        _ => {
+            let msg = format!("expected 1 expression, found {}", nodes.len());
+            Err(RuntimeError::new(ErrorKind::ValueError, msg))
+        }
+    }
+}
+
+/// Calls a function with arguments from a list, like
+/// `(apply + (list 1 2 3))`. Arguments before the list are passed
+/// first, so `(apply + 1 (list 2 3))` is the same.
+pub fn apply(inter: &mut Interpreter, mut _in: Vec<Value>) -> Result<Vec<Value>, RuntimeError> {
+    if _in.len() < 2 {
+        return Err(RuntimeError::arity("apply", 2, usize::MAX, _in.len()));
+    }
+
+    let last = _in.pop().unwrap();
+    let func = _in.remove(0);
+    match last.iter() {
+        Some(rest) => inter.extend_list(&mut _in, rest)?,
+        None => {
+            let msg = format!("can not apply a function to {}", last.type_name());
+            return Err(RuntimeError::new(ErrorKind::TypeError, msg));
+        }
+    }
+
+    Ok(vec![inter.call_value(&func, _in)?])
+}
+
+/// Returns the symbols defined by this module, to be loaded into the
+/// interpreter's global scope.
+pub fn symbols() -> HashMap<String, Value> {
+    let mut h = HashMap::new();
+    h.insert("println".into(), Value::RustFn(Rc::new(println)));
+    h.insert("print".into(), Value::RustFn(Rc::new(print)));
+    h.insert("input".into(), Value::RustFn(Rc::new(input)));
+    h.insert("throw".into(), Value::RustFn(Rc::new(throw)));
+    h.insert("error".into(), Value::RustFn(Rc::new(error)));
+    h.insert("read-string".into(), Value::RustFn(Rc::new(read_string)));
+    h.insert("apply".into(), Value::RustFn(Rc::new(apply)));
+    h.insert("gensym".into(), Value::RustFn(Rc::new(gensym)));
+    h.insert("macroexpand-1".into(), Value::RustFn(Rc::new(macroexpand_1)));
+    h.insert("macroexpand".into(), Value::RustFn(Rc::new(macroexpand)));
+    h
 }
@@ -1,97 +1,684 @@
-use crate::risp::vm::{ErrorKind, Interpreter, RuntimeError, Value};
-use crate::risp::AstNode;
+use crate::risp::vm::{to_node, to_value, Control, ErrorKind, Interpreter, Lambda, MacroFn, RuntimeError, Value};
+use crate::risp::{AstNode, NodeKind};
+use indexmap::IndexMap;
 use std::collections::HashMap;
+use std::cell::OnceCell;
+use std::rc::Rc;
 
 macro_rules! err {
     ($kind:ident, $msg:expr) => {
-        Err(RuntimeError {
-            kind: ErrorKind::$kind,
-            msg: $msg.into(),
-        })
+        Err(RuntimeError::new(ErrorKind::$kind, $msg).into())
     };
 }
 
-fn set(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, RuntimeError> {
+/// Checks that a binding form like `(set name value)` has the right
+/// shape, and returns the name along with the evaluated value. `form`
+/// is the name of the form, which is used in errors.
+fn binding(inter: &mut Interpreter, form: &str, nodes: &[AstNode]) -> Result<(String, Value), Control> {
     if nodes.len() != 2 {
-        return err!(
-            ValueError,
-            format!("expected 2 arguments, found {}", nodes.len())
-        );
+        return Err(RuntimeError::arity(form, 2, 2, nodes.len()).into());
     }
 
-    if let AstNode::Name(name) = &nodes[0] {
-        let value = inter.eval(&nodes[1]).unwrap();
-        inter.set_name(&name, value.clone());
-        Ok(value)
+    if let NodeKind::Name(name) = &nodes[0].kind {
+        let value = inter.eval(&nodes[1])?;
+        Ok((name.clone(), value))
     } else {
-        return err!(ValueError, format!("first argument must be a name"));
+        err!(ValueError, "first argument must be a name")
     }
 }
 
-fn list(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, RuntimeError> {
+/// Updates the nearest binding of a name, or defines it in the
+/// current scope if it is not bound yet.
+fn set(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    let (name, value) = binding(inter, "set", nodes)?;
+    inter.set_name(&name, value.clone());
+    Ok(value)
+}
+
+/// Defines a name in the current scope, like `(define x 1)`
+fn define(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    let (name, value) = binding(inter, "define", nodes)?;
+    inter.define_name(&name, value.clone());
+    Ok(value)
+}
+
+/// Updates the nearest binding of a name, like `(set! x 1)`. The name
+/// must already be bound.
+fn set_bang(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    let (name, value) = binding(inter, "set!", nodes)?;
+    inter.assign_name(&name, value.clone())?;
+    Ok(value)
+}
+
+/// Evaluates a body with local bindings, like
+/// `(let ((x 1) (y (+ x 1))) (* x y))`. Bindings are evaluated in
+/// order, so later bindings can refer to earlier ones.
+fn let_block(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    let tail = inter.take_tail_position();
+    let bindings = match nodes.first().map(|n| &n.kind) {
+        Some(NodeKind::Expr(bindings)) => bindings,
+        _ => return err!(ValueError, "expected a list of bindings"),
+    };
+
+    inter.with_scope(inter.env(), |inter| {
+        for pair in bindings {
+            match &pair.kind {
+                NodeKind::Expr(pair) if pair.len() == 2 => define(inter, pair)?,
+                _ => return err!(ValueError, "bindings must be of the form (name value)"),
+            };
+        }
+
+        body(inter, &nodes[1..], tail)
+    })
+}
+
+fn list(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
     let mut elems: Vec<Value> = Vec::new();
     for node in nodes {
-        elems.push(inter.eval(&node)?);
+        elems.push(inter.eval(node)?);
     }
 
     Ok(Value::List(elems))
 }
 
-fn block(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, RuntimeError> {
-    let mut res = Value::Null;
-    for node in nodes {
-        res = inter.eval(&node)?;
+/// Evaluates expressions in order, returning the value of the last one.
+/// The last expression is in tail position if `tail` is `true`.
+fn body(inter: &mut Interpreter, nodes: &[AstNode], tail: bool) -> Result<Value, Control> {
+    let (last, init) = match nodes.split_last() {
+        Some(split) => split,
+        None => return Ok(Value::Null),
+    };
+
+    for node in init {
+        inter.eval(node)?;
     }
 
-    Ok(res)
+    inter.eval_tail(last, tail)
+}
+
+fn block(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    let tail = inter.take_tail_position();
+    body(inter, nodes, tail)
 }
 
-fn if_else(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, RuntimeError> {
-    let has_else;
-    match nodes.len() {
-        2 => has_else = false,
-        3 => has_else = true,
-        _ => return err!(ValueError, "Incorrect number of arguments"),
+fn if_else(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    let tail = inter.take_tail_position();
+    let has_else = match nodes.len() {
+        2 => false,
+        3 => true,
+        n => return Err(RuntimeError::arity("if", 2, 3, n).into()),
     };
 
     let cond = &nodes[0];
     let if_expr = &nodes[1];
 
     if let Value::Bool(true) = inter.eval(cond)? {
-        inter.eval(if_expr)
+        inter.eval_tail(if_expr, tail)
     } else {
-        if has_else == false {
+        if !has_else {
             return Ok(Value::Null);
         }
 
         let else_expr = &nodes[2];
-        inter.eval(&else_expr)
+        inter.eval_tail(else_expr, tail)
+    }
+}
+
+/// Evaluates an argument of a logic macro, which must be a bool.
+/// Errors point at the argument itself.
+fn eval_bool(inter: &mut Interpreter, name: &str, node: &AstNode) -> Result<bool, Control> {
+    match inter.eval(node)? {
+        Value::Bool(b) => Ok(b),
+        other => {
+            let msg = format!("{name} expected a bool, found {}", other.type_name());
+            let mut error = RuntimeError::new(ErrorKind::TypeError, msg);
+            error.span = Some(node.span);
+            Err(error.into())
+        }
+    }
+}
+
+/// Returns `true` if every argument is `true`, like `(and a b)`.
+/// Arguments after the first `false` one are not evaluated.
+fn and(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    for node in nodes {
+        if !eval_bool(inter, "and", node)? {
+            return Ok(Value::Bool(false));
+        }
+    }
+
+    Ok(Value::Bool(true))
+}
+
+/// Returns `true` if any argument is `true`, like `(or a b)`.
+/// Arguments after the first `true` one are not evaluated.
+fn or(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    for node in nodes {
+        if eval_bool(inter, "or", node)? {
+            return Ok(Value::Bool(true));
+        }
     }
+
+    Ok(Value::Bool(false))
 }
 
-fn while_loop(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, RuntimeError> {
-    if nodes.len() < 1 {
-        return err!(ValueError, "Not enough arguments");
+/// Negates a bool, like `(not a)`
+fn not(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    match nodes {
+        [node] => Ok(Value::Bool(!eval_bool(inter, "not", node)?)),
+        _ => Err(RuntimeError::arity("not", 1, 1, nodes.len()).into()),
     }
+}
 
-    let condition = &nodes[0];
+/// Runs a loop, calling `iteration` until it returns `None`. The loop
+/// evaluates to the value of the last iteration, or to the value given
+/// to `break`. An iteration that is skipped with `continue` has a value
+/// of null.
+/// 
+/// Every iteration takes a step, so that loops with empty bodies still
+/// stop at the step limit.
+fn run_loop(
+    inter: &mut Interpreter,
+    mut iteration: impl FnMut(&mut Interpreter) -> Result<Option<Value>, Control>,
+) -> Result<Value, Control> {
     let mut value = Value::Null;
 
-    while let Value::Bool(true) = inter.eval(condition)? {
-        value = block(inter, &nodes)?
+    inter.loops += 1;
+    let result = loop {
+        if let Err(err) = inter.tick() {
+            break Err(err.into());
+        }
+
+        match iteration(inter) {
+            Ok(Some(result)) => value = result,
+            Ok(None) => break Ok(value),
+            Err(Control::Break(result)) => break Ok(result),
+            Err(Control::Continue) => value = Value::Null,
+            Err(control) => break Err(control),
+        }
+    };
+    inter.loops -= 1;
+
+    result
+}
+
+/// Evaluates a body while a condition is true, like
+/// `(while (< i 10) (set i (+ i 1)))`
+fn while_loop(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    let (condition, nodes) = match nodes.split_first() {
+        Some(split) => split,
+        None => return Err(RuntimeError::arity("while", 1, usize::MAX, 0).into()),
+    };
+
+    run_loop(inter, |inter| match inter.eval(condition)? {
+        // Each iteration gets its own scope, so names defined in the body
+        // do not leak out of the loop.
+        Value::Bool(true) => inter.with_scope(inter.env(), |inter| body(inter, nodes, false)).map(Some),
+        _ => Ok(None),
+    })
+}
+
+/// Binds a value to a pattern, which is either a name or a list of
+/// names like `(key value)`. Lists are unpacked into the names.
+fn bind_pattern(inter: &mut Interpreter, pattern: &AstNode, value: Value) -> Result<(), Control> {
+    let names = match &pattern.kind {
+        NodeKind::Name(name) => {
+            inter.define_name(name, value);
+            return Ok(());
+        }
+        NodeKind::Expr(names) => names,
+        _ => return err!(ValueError, "expected a name or a list of names to bind"),
+    };
+
+    let values = match value {
+        Value::List(values) if values.len() == names.len() => values,
+        other => {
+            return err!(
+                ValueError,
+                format!("can not unpack {} into {} names", other.repr(), names.len())
+            )
+        }
+    };
+
+    for (name, value) in names.iter().zip(values) {
+        bind_pattern(inter, name, value)?;
+    }
+    Ok(())
+}
+
+/// Evaluates a body once for every value produced by an iterator, with
+/// the value bound to a pattern in a fresh scope.
+fn iterate(
+    inter: &mut Interpreter,
+    pattern: &AstNode,
+    mut values: impl Iterator<Item = Value>,
+    nodes: &[AstNode],
+) -> Result<Value, Control> {
+    run_loop(inter, |inter| match values.next() {
+        Some(value) => inter
+            .with_scope(inter.env(), |inter| {
+                bind_pattern(inter, pattern, value)?;
+                body(inter, nodes, false)
+            })
+            .map(Some),
+        None => Ok(None),
+    })
+}
+
+/// Splits a looping form like `(for (x coll) body...)` into the
+/// pattern, the node that produces the values, and the body.
+fn loop_header<'a>(
+    name: &str,
+    nodes: &'a [AstNode],
+) -> Result<(&'a AstNode, &'a AstNode, &'a [AstNode]), Control> {
+    match nodes.split_first() {
+        Some((AstNode { kind: NodeKind::Expr(header), .. }, body)) if header.len() == 2 => {
+            Ok((&header[0], &header[1], body))
+        }
+        _ => err!(
+            ValueError,
+            format!("{name} must be of the form ({name} (name value) body...)")
+        ),
+    }
+}
+
+/// Evaluates a body for each element of a list, set, string, map or
+/// range, like `(for (x (list 1 2 3)) (println x))`. The entries of a
+/// map can be unpacked, like `(for ((key value) m) ...)`.
+fn for_loop(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    let (pattern, coll, nodes) = loop_header("for", nodes)?;
+    let coll = inter.eval(coll)?;
+
+    let values = match coll.iter() {
+        Some(values) => values,
+        None => {
+            let msg = format!("{} is not iterable", coll.type_name());
+            return Err(RuntimeError::new(ErrorKind::TypeError, msg).into());
+        }
+    };
+
+    iterate(inter, pattern, values, nodes)
+}
+
+/// Evaluates a body a number of times, with a name bound to the number
+/// of the iteration counting from 0, like `(dotimes (i 10) (println i))`
+fn dotimes(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    let (pattern, times, nodes) = loop_header("dotimes", nodes)?;
+
+    let times = match inter.eval(times)? {
+        Value::Int(times) => times,
+        other => {
+            let msg = format!("dotimes expected an int, found {}", other.type_name());
+            return Err(RuntimeError::new(ErrorKind::TypeError, msg).into());
+        }
+    };
+
+    iterate(inter, pattern, (0..times).map(Value::Int), nodes)
+}
+
+/// Returns the value of an optional argument to `name`, or null if it
+/// is missing
+fn optional_value(
+    inter: &mut Interpreter,
+    name: &str,
+    nodes: &[AstNode],
+) -> Result<Value, Control> {
+    match nodes {
+        [] => Ok(Value::Null),
+        [node] => inter.eval(node),
+        _ => Err(RuntimeError::arity(name, 0, 1, nodes.len()).into()),
+    }
+}
+
+/// Leaves the innermost loop, like `(break)` or `(break value)`. The
+/// loop evaluates to the value, or null if there is none.
+fn break_loop(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    if inter.loops == 0 {
+        return err!(ControlError, "break outside of a loop");
+    }
+
+    Err(Control::Break(optional_value(inter, "break", nodes)?))
+}
+
+/// Skips the rest of the current iteration of the innermost loop, like
+/// `(continue)`
+fn continue_loop(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    if inter.loops == 0 {
+        return err!(ControlError, "continue outside of a loop");
+    }
+    if !nodes.is_empty() {
+        return Err(RuntimeError::arity("continue", 0, 0, nodes.len()).into());
+    }
+
+    Err(Control::Continue)
+}
+
+/// Returns from the innermost function, like `(return)` or
+/// `(return value)`. The function returns null if there is no value.
+fn return_fn(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    if inter.functions == 0 {
+        return err!(ControlError, "return outside of a function");
+    }
+
+    Err(Control::Return(optional_value(inter, "return", nodes)?))
+}
+
+/// Returns the clause of a `try` form with the given head, like
+/// `(catch e ...)`, if it is the last node.
+fn clause<'a>(nodes: &'a [AstNode], head: &str) -> Option<(&'a [AstNode], &'a [AstNode])> {
+    let (last, init) = nodes.split_last()?;
+    match &last.kind {
+        NodeKind::Expr(clause) => match clause.first().map(|n| &n.kind) {
+            Some(NodeKind::Name(name)) if name == head => Some((init, &clause[1..])),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Converts a caught error into the value bound by `catch`. Values
+/// raised with `throw` are bound unchanged. Other errors are described
+/// by a map with their `"kind"`, `"message"` and `"span"`, which is
+/// the location as `file:line:column`.
+fn error_value(inter: &Interpreter, error: RuntimeError) -> Value {
+    if let Some(value) = error.value {
+        return *value;
+    }
+
+    let span = match error.span.and_then(|span| inter.sources().location(span)) {
+        Some(location) => Value::Str(location),
+        None => Value::Null,
+    };
+
+    let mut map = IndexMap::new();
+    map.insert(Value::Str("kind".into()), Value::Str(format!("{:?}", error.kind)));
+    map.insert(Value::Str("message".into()), Value::Str(error.msg));
+    map.insert(Value::Str("span".into()), span);
+    Value::Map(map)
+}
+
+/// Evaluates a body, handling any errors it raises, like
+/// `(try body... (catch e handler...) (finally cleanup...))`.
+///
+/// If the body fails, the error is bound to the name in the `catch`
+/// clause, and the value of the handler is returned instead. The
+/// `finally` clause is evaluated afterwards in every case, and its
+/// value is ignored. Either clause can be left out.
+///
+/// `LimitExceeded` errors are never caught, so that code can not
+/// keep running past the interpreter's step limit. Control flow, like
+/// `break`, passes through the `try` after running the `finally`.
+fn try_catch(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    let (nodes, finally) = match clause(nodes, "finally") {
+        Some((init, finally)) => (init, Some(finally)),
+        None => (nodes, None),
+    };
+    let (nodes, catch) = match clause(nodes, "catch") {
+        Some((init, catch)) => (init, Some(catch)),
+        None => (nodes, None),
+    };
+
+    let handler = match catch {
+        Some([name, handler @ ..]) => match &name.kind {
+            NodeKind::Name(name) => Some((name, handler)),
+            _ => return err!(ValueError, "catch must be of the form (catch name body...)"),
+        },
+        Some([]) => return err!(ValueError, "catch expects a name to bind the error to"),
+        None => None,
+    };
+
+    let result = match (body(inter, nodes, false), handler) {
+        (Err(Control::Error(error)), Some((name, handler)))
+            if !matches!(error.kind, ErrorKind::LimitExceeded) =>
+        {
+            let error = error_value(inter, error);
+            inter.with_scope(inter.env(), |inter| {
+                inter.define_name(name, error);
+                body(inter, handler, false)
+            })
+        }
+        (result, _) => result,
+    };
+
+    if let Some(finally) = finally {
+        body(inter, finally, false)?;
     }
 
-    return Ok(value);
+    result
 }
 
-lazy_static! {
-    pub static ref SYMBOLS: HashMap<String, Value> = {
-        let mut h = HashMap::new();
-        h.insert("set".into(), Value::RustMacro(set));
-        h.insert("list".into(), Value::RustMacro(list));
-        h.insert("block".into(), Value::RustMacro(block));
-        h.insert("if".into(), Value::RustMacro(if_else));
-        h.insert("while".into(), Value::RustMacro(while_loop));
-        h
+/// Creates a [`Lambda`] from a parameter list and a body. The lambda
+/// captures the scope that it is created in.
+fn make_lambda(
+    inter: &mut Interpreter,
+    name: Option<String>,
+    nodes: &[AstNode],
+) -> Result<Lambda, Control> {
+    if nodes.is_empty() {
+        return err!(ValueError, "expected a parameter list");
+    }
+
+    let params = match &nodes[0].kind {
+        NodeKind::Expr(params) => params,
+        _ => return err!(ValueError, "parameter list must be an expression"),
     };
+
+    let mut names = Vec::new();
+    let mut rest = None;
+    let mut params = params.iter();
+    while let Some(param) = params.next() {
+        match &param.kind {
+            // The parameter after a `&` collects any extra arguments
+            NodeKind::Name(n) if n == "&" => match (params.next().map(|p| &p.kind), params.next()) {
+                (Some(NodeKind::Name(n)), None) => rest = Some(n.clone()),
+                _ => return err!(ValueError, "& must be followed by exactly one name"),
+            },
+            NodeKind::Name(n) => names.push(n.clone()),
+            _ => return err!(ValueError, "parameters must be names"),
+        }
+    }
+
+    let lambda = Lambda {
+        name,
+        params: names,
+        rest,
+        body: nodes[1..].to_vec(),
+        env: inter.env(),
+        chunk: OnceCell::new(),
+        files: inter.hold_files(nodes.iter().map(|node| node.span)),
+    };
+
+    Ok(lambda)
+}
+
+/// Creates an anonymous function, like `(fn (a b) (+ a b))`
+fn fn_lambda(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    Ok(Value::Lambda(Rc::new(make_lambda(inter, None, nodes)?)))
+}
+
+/// Returns the name that a form like `(defn name ...)` defines
+fn defined_name(nodes: &[AstNode]) -> Result<String, Control> {
+    match nodes.first().map(|n| &n.kind) {
+        Some(NodeKind::Name(name)) => Ok(name.clone()),
+        _ => err!(ValueError, "first argument must be a name"),
+    }
+}
+
+/// Creates a named function and binds it to its name, like
+/// `(defn add (a b) (+ a b))`
+fn defn(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    let name = defined_name(nodes)?;
+    let lambda = Value::Lambda(Rc::new(make_lambda(inter, Some(name.clone()), &nodes[1..])?));
+    inter.define_name(&name, lambda.clone());
+    Ok(lambda)
+}
+
+/// Creates a macro and binds it to its name, like
+/// ``(defmacro unless (cond & body) `(if ,cond null (block ,@body)))``.
+///
+/// When the macro is called, its arguments are passed to it as data,
+/// without being evaluated. The macro returns data, which is converted
+/// back into code and evaluated in place of the call.
+fn defmacro(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    let name = defined_name(nodes)?;
+    let mac = Value::Macro(Rc::new(make_lambda(inter, Some(name.clone()), &nodes[1..])?));
+    inter.define_name(&name, mac.clone());
+    Ok(mac)
+}
+
+/// Returns the argument of a form like `(unquote x)`, if the node is
+/// one.
+fn unquoted<'a>(node: &'a AstNode, form: &str) -> Option<&'a AstNode> {
+    match &node.kind {
+        NodeKind::Expr(nodes) => match nodes.as_slice() {
+            [AstNode { kind: NodeKind::Name(name), .. }, arg] if name == form => Some(arg),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Converts code into data, like `quote`, except that expressions
+/// inside `(unquote x)` are evaluated, and lists inside
+/// `(unquote-splicing x)` are spliced into the surrounding list.
+fn quasi(inter: &mut Interpreter, node: &AstNode) -> Result<Value, Control> {
+    if let Some(arg) = unquoted(node, "unquote") {
+        return inter.eval(arg);
+    }
+
+    let elems = match &node.kind {
+        NodeKind::Expr(elems) => elems,
+        // Quoted lists can contain unquotes too
+        NodeKind::List(elems) => {
+            let list = quasi_list(inter, elems)?;
+            return Ok(Value::List(vec![Value::Symbol("quote".into()), list]));
+        }
+        _ => return Ok(to_value(node)),
+    };
+
+    quasi_list(inter, elems)
+}
+
+/// Converts the elements of a list for [`quasi`], splicing in any
+/// `(unquote-splicing x)` forms.
+fn quasi_list(inter: &mut Interpreter, elems: &[AstNode]) -> Result<Value, Control> {
+    let mut list = Vec::new();
+    for elem in elems {
+        let arg = match unquoted(elem, "unquote-splicing") {
+            Some(arg) => arg,
+            None => {
+                list.push(quasi(inter, elem)?);
+                continue;
+            }
+        };
+
+        let value = inter.eval(arg)?;
+        let Some(values) = value.iter() else {
+            let msg = format!("can not splice {} into a list", value.type_name());
+            return Err(RuntimeError::new(ErrorKind::TypeError, msg).into());
+        };
+        inter.extend_list(&mut list, values)?;
+    }
+
+    Ok(Value::List(list))
+}
+
+/// Converts code into data, filling in parts of it, like
+/// ``(quasiquote (+ 1 (unquote x)))``, which is usually written as
+/// `` `(+ 1 ,x) ``
+fn quasiquote(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    match nodes {
+        [node] => quasi(inter, node),
+        _ => Err(RuntimeError::arity("quasiquote", 1, 1, nodes.len()).into()),
+    }
+}
+
+/// Converts code into data without evaluating it, like
+/// `(quote (+ 1 2))`, which is usually written as `'(+ 1 2)`
+fn quote(_: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    match nodes {
+        [node] => Ok(to_value(node)),
+        _ => Err(RuntimeError::arity("quote", 1, 1, nodes.len()).into()),
+    }
+}
+
+/// Evaluates data as code in the current scope, like
+/// `(eval (list + 1 2))`. Errors in the code point at the argument to
+/// `eval`.
+fn eval(inter: &mut Interpreter, nodes: &[AstNode]) -> Result<Value, Control> {
+    let tail = inter.take_tail_position();
+    let node = match nodes {
+        [node] => node,
+        _ => return Err(RuntimeError::arity("eval", 1, 1, nodes.len()).into()),
+    };
+
+    let data = inter.eval(node)?;
+    let code = to_node(&data, node.span)?;
+    inter.eval_tail(&code, tail)
+}
+
+/// `unquote` and `unquote-splicing` are handled by `quasiquote`, so
+/// they are errors anywhere else.
+fn unquote(_: &mut Interpreter, _: &[AstNode]) -> Result<Value, Control> {
+    err!(ValueError, "unquote can only be used inside a quasiquote")
+}
+
+/// Returns the built-in macro of a special form that the compiler lowers
+/// into bytecode, like `if` or `let`.
+pub(crate) fn special_form(name: &str) -> Option<MacroFn> {
+    let form: MacroFn = match name {
+        "if" => if_else,
+        "block" => block,
+        "while" => while_loop,
+        "list" => list,
+        "set" => set,
+        "define" => define,
+        "set!" => set_bang,
+        "let" => let_block,
+        _ => return None,
+    };
+    Some(form)
+}
+
+/// Returns `true` if a built-in macro never binds names in, or
+/// captures, the scope that it is called in. The compiler can store
+/// the bindings of a `let` in local slots if its body only calls these.
+pub(crate) fn never_binds(func: MacroFn) -> bool {
+    let forms: [MacroFn; 18] = [
+        set_bang, let_block, list, block, if_else, while_loop, for_loop, dotimes, break_loop,
+        continue_loop, return_fn, and, or, not, try_catch, quote, quasiquote, unquote,
+    ];
+    forms.iter().any(|form| std::ptr::fn_addr_eq(*form, func))
+}
+
+/// Returns the symbols defined by this module, to be loaded into the
+/// interpreter's global scope.
+pub fn symbols() -> HashMap<String, Value> {
+    let mut h = HashMap::new();
+    h.insert("set".into(), Value::RustMacro(set));
+    h.insert("define".into(), Value::RustMacro(define));
+    h.insert("set!".into(), Value::RustMacro(set_bang));
+    h.insert("let".into(), Value::RustMacro(let_block));
+    h.insert("list".into(), Value::RustMacro(list));
+    h.insert("block".into(), Value::RustMacro(block));
+    h.insert("if".into(), Value::RustMacro(if_else));
+    h.insert("while".into(), Value::RustMacro(while_loop));
+    h.insert("for".into(), Value::RustMacro(for_loop));
+    h.insert("dotimes".into(), Value::RustMacro(dotimes));
+    h.insert("break".into(), Value::RustMacro(break_loop));
+    h.insert("continue".into(), Value::RustMacro(continue_loop));
+    h.insert("return".into(), Value::RustMacro(return_fn));
+    h.insert("and".into(), Value::RustMacro(and));
+    h.insert("or".into(), Value::RustMacro(or));
+    h.insert("not".into(), Value::RustMacro(not));
+    h.insert("try".into(), Value::RustMacro(try_catch));
+    h.insert("fn".into(), Value::RustMacro(fn_lambda));
+    h.insert("defn".into(), Value::RustMacro(defn));
+    h.insert("defmacro".into(), Value::RustMacro(defmacro));
+    h.insert("quote".into(), Value::RustMacro(quote));
+    h.insert("eval".into(), Value::RustMacro(eval));
+    h.insert("quasiquote".into(), Value::RustMacro(quasiquote));
+    h.insert("unquote".into(), Value::RustMacro(unquote));
+    h.insert("unquote-splicing".into(), Value::RustMacro(unquote));
+    h
 }
@@ -1,2 +1,3 @@
+pub mod collections;
 pub mod functions;
-pub mod macros;
\ No newline at end of file
+pub mod macros;
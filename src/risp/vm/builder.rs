@@ -0,0 +1,132 @@
+//! A builder for configuring an [`Interpreter`] before it is created,
+//! such as choosing which parts of the standard library it loads.
+
+use std::fmt;
+use std::io::{BufRead, Write};
+
+use super::{Interpreter, Limits, Scope, Value};
+use crate::risp::stdlib;
+
+/// Configures and creates an [`Interpreter`]. Every module of the
+/// standard library is loaded unless it is turned off:
+///
+/// ```
+/// use risp::{Interpreter, Limits};
+///
+/// let mut interpreter = Interpreter::builder()
+///     .functions(false)
+///     .limits(Limits { max_steps: Some(10_000), ..Limits::default() })
+///     .build();
+///
+/// assert!(interpreter.eval_str("(println 1)").is_err());
+/// ```
+pub struct InterpreterBuilder {
+    functions: bool,
+    macros: bool,
+    collections: bool,
+    limits: Limits,
+    output: Option<Box<dyn Write>>,
+    input: Option<Box<dyn BufRead>>,
+}
+
+impl fmt::Debug for InterpreterBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InterpreterBuilder")
+            .field("functions", &self.functions)
+            .field("macros", &self.macros)
+            .field("collections", &self.collections)
+            .field("limits", &self.limits)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for InterpreterBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InterpreterBuilder {
+    /// Creates a builder which loads the whole standard library and
+    /// uses the default [`Limits`].
+    pub fn new() -> Self {
+        Self {
+            functions: true,
+            macros: true,
+            collections: true,
+            limits: Limits::default(),
+            output: None,
+            input: None,
+        }
+    }
+
+    /// Whether to load the general functions, like `println`, `input`,
+    /// `throw`, `read-string` and `apply`.
+    pub fn functions(mut self, load: bool) -> Self {
+        self.functions = load;
+        self
+    }
+
+    /// Whether to load the special forms, like `define`, `if`, `fn`,
+    /// the loops and `try`. Very little code can run without these.
+    pub fn macros(mut self, load: bool) -> Self {
+        self.macros = load;
+        self
+    }
+
+    /// Whether to load the functions that work on lists, maps and
+    /// sets, like `get`, `assoc` and `range`.
+    pub fn collections(mut self, load: bool) -> Self {
+        self.collections = load;
+        self
+    }
+
+    /// Sets the limits on the resources that code can use.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets where `print` and `println` write to, instead of STDOUT.
+    /// See [`Interpreter::set_output`].
+    pub fn output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    /// Sets where `input` reads lines from, instead of STDIN. See
+    /// [`Interpreter::set_input`].
+    pub fn input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    /// Creates the interpreter
+    pub fn build(self) -> Interpreter {
+        let env = Scope::new(None);
+        {
+            let mut scope = env.borrow_mut();
+            let modules = [
+                (self.functions, stdlib::functions::symbols as fn() -> _),
+                (self.macros, stdlib::macros::symbols),
+                (self.collections, stdlib::collections::symbols),
+            ];
+            for (_, symbols) in modules.iter().filter(|(load, _)| *load) {
+                for (name, value) in symbols() {
+                    scope.define(&name, value);
+                }
+            }
+            scope.define("true", Value::Bool(true));
+            scope.define("false", Value::Bool(false));
+            scope.define("null", Value::Null);
+        }
+
+        let mut interpreter = Interpreter::with_globals(env, self.limits);
+        interpreter.special_forms = self.macros;
+        if let Some(output) = self.output {
+            interpreter.output = output;
+        }
+        interpreter.input = self.input;
+        interpreter
+    }
+}
@@ -0,0 +1,153 @@
+use crate::risp::{shared::Op, AstNode, Span};
+
+use super::{MacroFn, Value};
+use crate::risp::stdlib::macros;
+
+/// A single bytecode instruction, executed by the interpreter's
+/// dispatch loop. Instructions operate on a stack of values.
+/// 
+/// Indices into the pools of a [`Chunk`] are stored as `u32`s, and
+/// local slots and argument counts as `u16`s, to keep instructions
+/// small.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instr {
+    /// Pushes a value from the constant pool
+    Const(u32),
+    /// Pushes the value bound to a name from the name pool
+    LoadName(u32),
+    /// Like [`Instr::LoadName`], but used for the callees of calls,
+    /// which are always followed by an [`Instr::MacroCheck`]. If the
+    /// name is not bound, nothing is pushed. Instead, the whole call is
+    /// evaluated by the tree-walking interpreter, which reports the
+    /// error or calls a method with the name, and execution jumps to
+    /// the macro check's `skip`.
+    LoadCallee(u32),
+    /// Pushes the value of a local slot
+    LoadLocal(u16),
+    /// Pops a value into a local slot
+    StoreLocal(u16),
+    /// Binds the value on top of the stack to a name, with the same
+    /// semantics as [`Interpreter::set_name`](super::Interpreter::set_name).
+    /// The value is left on the stack.
+    SetName(u32),
+    /// Binds the value on top of the stack to a name in the current
+    /// scope. The value is left on the stack.
+    DefineName(u32),
+    /// Updates the nearest binding of a name to the value on top of the
+    /// stack. The value is left on the stack.
+    AssignName(u32),
+    /// Discards the value on top of the stack
+    Pop,
+    /// Jumps to an instruction
+    Jump(u32),
+    /// Pops a value, and jumps to an instruction if it is not `true`
+    JumpIfFalse(u32),
+    /// Starts a loop, which ends at the [`Instr::PopLoop`] at an index.
+    /// `break` and `continue` inside the loop restore the stack and
+    /// scope to what they were here, then jump to the end of the loop
+    /// or to the next instruction.
+    PushLoop(u32),
+    /// Ends the innermost loop
+    PopLoop,
+    /// Enters a new scope, nested in the current one
+    PushScope,
+    /// Leaves the current scope
+    PopScope,
+    /// Checks whether the value on top of the stack is a macro. If it
+    /// is, the macro is popped and called with the arguments of the
+    /// expression at index `node` of the node pool, its result is
+    /// pushed, and execution jumps to `skip`. Otherwise, execution
+    /// continues normally, so that the arguments are evaluated and the
+    /// value is called as a function.
+    /// 
+    /// Any local slots listed in `locals` are made visible to the macro.
+    MacroCheck { node: u32, locals: u32, skip: u32 },
+    /// Checks that the names in the guard at index `guard` of the guard
+    /// pool are still bound to what the compiler assumed. If they are,
+    /// execution continues normally. Otherwise, the node at index `node`
+    /// of the node pool is evaluated by the tree-walking interpreter, its
+    /// result is pushed, and execution jumps to `skip`.
+    /// 
+    /// Any local slots listed in `locals` are made visible to the node.
+    Guard { guard: u32, node: u32, locals: u32, skip: u32 },
+    /// Pops `argc` arguments and a callee, calls the callee with the
+    /// arguments, and pushes the result. `name` indexes the name pool,
+    /// and is used in backtraces.
+    Call { argc: u16, name: u32 },
+    /// Like [`Instr::Call`], but used for calls in tail position. If
+    /// the callee is a function defined in risp, and the current chunk
+    /// is a function body, the callee replaces the current function
+    /// instead of returning to it.
+    TailCall { argc: u16, name: u32 },
+    /// Pops `argc` operands, applies an operator to them, and pushes
+    /// the result.
+    CallOp { op: Op, argc: u16 },
+    /// Pops `argc` arguments, calls a method of the first one with the
+    /// rest, and pushes the result. `name` indexes the name pool, and
+    /// is the method's name with a leading `.`, like `.read`.
+    CallMethod { argc: u16, name: u32 },
+    /// Pops a number of values, and pushes them as a list
+    MakeList(u32),
+    /// Pops a number of key-value pairs, and pushes them as a map
+    MakeMap(u32),
+    /// Pops a number of values, and pushes them as a set
+    MakeSet(u32),
+    /// Evaluates the node at index `node` of the node pool using the
+    /// tree-walking interpreter, and pushes the result. This is used
+    /// for forms that the compiler does not know how to lower.
+    /// 
+    /// Any local slots listed in `locals` are made visible to the node.
+    Eval { node: u32, locals: u32 },
+    /// Returns the value on top of the stack from the current chunk
+    Return,
+}
+
+/// A compiled piece of code, along with the pools that its
+/// instructions refer to.
+#[derive(Default)]
+pub struct Chunk {
+    /// The instructions of the chunk
+    pub code: Vec<Instr>,
+    /// The span of source code that each instruction was compiled from
+    pub spans: Vec<Span>,
+    /// The constant pool
+    pub constants: Vec<Value>,
+    /// The name pool, used for looking up and binding names
+    pub names: Vec<String>,
+    /// The node pool, used by instructions that fall back to the
+    /// tree-walking interpreter
+    pub nodes: Vec<AstNode>,
+    /// Sets of local slots, along with their names, which have to be
+    /// made visible to the tree-walking interpreter
+    pub locals: Vec<Vec<(String, u16)>>,
+    /// The guard pool, which lists the names that each [`Instr::Guard`]
+    /// checks, as indices into the name pool
+    pub guards: Vec<Vec<(u32, Assumption)>>,
+    /// The number of local slots used by the chunk
+    pub num_locals: u16,
+}
+
+/// What the compiler assumed a name to be bound to when it lowered some
+/// code, which an [`Instr::Guard`] checks when the code runs.
+#[derive(Clone, Copy, Debug)]
+pub enum Assumption {
+    /// The name is bound to the built-in macro of a special form
+    Builtin(MacroFn),
+    /// The name is bound to something that never binds names in the
+    /// scope that it is called in
+    NoBindings,
+}
+
+impl Assumption {
+    /// Returns `true` if the assumption holds for the value that a name
+    /// is bound to, if it is bound
+    pub fn holds(&self, value: Option<&Value>) -> bool {
+        match (self, value) {
+            (Assumption::Builtin(form), Some(Value::RustMacro(func))) => std::ptr::fn_addr_eq(*form, *func),
+            (Assumption::Builtin(_), _) => false,
+            (Assumption::NoBindings, Some(Value::RustMacro(func))) => macros::never_binds(*func),
+            (Assumption::NoBindings, Some(Value::Macro(_)) | None) => false,
+            (Assumption::NoBindings, Some(_)) => true,
+        }
+    }
+}
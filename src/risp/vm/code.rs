@@ -0,0 +1,105 @@
+//! Conversions between code and data, which let macros written in risp
+//! work on code as if it were ordinary values.
+//!
+//! Expressions become lists, and names become symbols. Literals stay as
+//! they are. A quoted name like `'x` becomes the list `(quote x)`, so
+//! that it can be told apart from the name `x`.
+//!
+//! Converting data to code and back gives the same data, except for
+//! ratios, which become divisions like `(/ 1 3)`.
+
+use super::{number, ErrorKind, RuntimeError, Value};
+use crate::risp::{shared::grow_stack, AstNode, NodeKind, Op, Span};
+
+/// Converts code into data. This is also the value of the code when it
+/// is quoted.
+pub fn to_value(node: &AstNode) -> Value {
+    grow_stack(|| node_value(node))
+}
+
+fn node_value(node: &AstNode) -> Value {
+    match &node.kind {
+        NodeKind::Int(i) => Value::Int(*i),
+        NodeKind::BigInt(i) => Value::BigInt(i.clone()),
+        NodeKind::Float(f) => Value::Float(*f),
+        NodeKind::Str(s) => Value::Str(s.clone()),
+        NodeKind::Operator(op) => Value::Operator(*op),
+        NodeKind::Name(name) => match name.as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "null" => Value::Null,
+            _ => Value::Symbol(name.clone()),
+        },
+
+        NodeKind::Symbol(s) => quoted(Value::Symbol(s.clone())),
+        NodeKind::List(elems) => quoted(Value::List(elems.iter().map(to_value).collect())),
+
+        NodeKind::Expr(nodes) => Value::List(nodes.iter().map(to_value).collect()),
+        NodeKind::Map(entries) => Value::Map(
+            entries
+                .chunks(2)
+                .map(|pair| (to_value(&pair[0]), to_value(&pair[1])))
+                .collect(),
+        ),
+        NodeKind::Set(elems) => Value::Set(elems.iter().map(to_value).collect()),
+    }
+}
+
+/// Wraps data in a `(quote x)` list
+fn quoted(value: Value) -> Value {
+    Value::List(vec![Value::Symbol("quote".into()), value])
+}
+
+/// Converts data into code. Every node is given the same span, which
+/// is usually the span of the macro call that produced the code.
+/// Returns a `TypeError` for values that have no equivalent in code,
+/// like functions.
+pub fn to_node(value: &Value, span: Span) -> Result<AstNode, RuntimeError> {
+    grow_stack(|| value_node(value, span))
+}
+
+fn value_node(value: &Value, span: Span) -> Result<AstNode, RuntimeError> {
+    let nodes = |values: &mut dyn Iterator<Item = &Value>| -> Result<Vec<AstNode>, RuntimeError> {
+        values.map(|value| to_node(value, span)).collect()
+    };
+
+    let kind = match value {
+        Value::Int(i) => NodeKind::Int(*i),
+        Value::BigInt(i) => NodeKind::BigInt(i.clone()),
+        Value::Float(f) => NodeKind::Float(*f),
+        Value::Str(s) => NodeKind::Str(s.clone()),
+        Value::Operator(op) => NodeKind::Operator(*op),
+        Value::Symbol(name) => NodeKind::Name(name.clone()),
+
+        // These values are bound to names in the global scope
+        Value::Bool(b) => NodeKind::Name(b.to_string()),
+        Value::Null => NodeKind::Name("null".into()),
+
+        // Ratios are written as a division, like `(/ 1 3)`
+        Value::Ratio(r) => {
+            let numer = to_node(&number::normalize_int(r.numer().clone()), span)?;
+            let denom = to_node(&number::normalize_int(r.denom().clone()), span)?;
+            let op = AstNode::new(NodeKind::Operator(Op::Slash), span);
+            NodeKind::Expr(vec![op, numer, denom])
+        }
+
+        Value::List(items) => match items.as_slice() {
+            [Value::Symbol(quote), Value::Symbol(s)] if quote == "quote" => NodeKind::Symbol(s.clone()),
+            [Value::Symbol(quote), Value::List(elems)] if quote == "quote" => {
+                NodeKind::List(nodes(&mut elems.iter())?)
+            }
+            _ => NodeKind::Expr(nodes(&mut items.iter())?),
+        },
+        Value::Map(entries) => {
+            NodeKind::Map(nodes(&mut entries.iter().flat_map(|(k, v)| [k, v]))?)
+        }
+        Value::Set(elems) => NodeKind::Set(nodes(&mut elems.iter())?),
+
+        other => {
+            let msg = format!("{} can not be converted to code", other.type_name());
+            return Err(RuntimeError::new(ErrorKind::TypeError, msg));
+        }
+    };
+
+    Ok(AstNode::new(kind, span))
+}
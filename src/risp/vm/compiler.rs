@@ -0,0 +1,618 @@
+//! The compiler lowers [`AstNode`]s into bytecode [`Chunk`]s.
+//! 
+//! Most special forms, like `if`, `while` and `block`, are compiled into
+//! jumps. `let` bindings are stored in local slots where that is safe.
+//! Forms that the compiler does not know how to lower, such as `fn`,
+//! fall back to the tree-walking interpreter.
+//! 
+//! Calls in tail position, which are the last expression of a function
+//! body, `block` or `let`, and the branches of an `if`, are compiled
+//! into [`Instr::TailCall`]s.
+//! 
+//! A special form is only lowered where its name is bound to the
+//! built-in macro when the code is compiled. Names can be rebound after
+//! that, so the lowered code starts with an [`Instr::Guard`], which
+//! leaves the form to the tree-walker if the name has been rebound by
+//! the time it runs.
+//! 
+//! Lowered special forms do not push frames onto the call stack, so
+//! unlike the tree-walker's, bytecode backtraces have no frames like
+//! `in if` or `in let`. Calls to functions and macros have the same
+//! frames in both engines.
+
+use std::collections::{HashMap, HashSet};
+
+use super::bytecode::{Assumption, Chunk, Instr};
+use super::{to_value, Env, MacroFn, Value};
+use crate::risp::{shared::grow_stack, stdlib::macros, AstNode, NodeKind, Span};
+
+/// A struct that compiles ASTs into a [`Chunk`]
+pub struct Compiler {
+    /// The chunk being compiled
+    chunk: Chunk,
+    /// Maps names to their index in the chunk's name pool
+    name_indices: HashMap<String, u32>,
+    /// The local slots that are currently in scope, innermost last
+    locals: Vec<(String, u16)>,
+    /// Whether special forms are lowered into bytecode
+    special_forms: bool,
+    /// The scope that callees are resolved in, to find out whether a
+    /// `let` body may bind names
+    env: Env,
+    /// Names whose values are not known until the code runs, because
+    /// the code binds them itself, or they are parameters
+    unknown: HashSet<String>,
+}
+
+impl Compiler {
+    /// Compiles a sequence of expressions into a chunk, which returns the
+    /// value of the last expression. The last expression is in tail
+    /// position.
+    /// 
+    /// Special forms like `if` are only lowered into bytecode if
+    /// `special_forms` is `true`. Otherwise they are called like any
+    /// other name.
+    /// 
+    /// Callees are resolved in `env`, the scope that the code will run
+    /// in, apart from `params`, which are bound when the code runs.
+    pub fn compile(nodes: &[AstNode], special_forms: bool, env: Env, params: &[String]) -> Chunk {
+        let mut unknown: HashSet<String> = params.iter().cloned().collect();
+        for node in nodes {
+            operand_names(node, &mut unknown);
+        }
+
+        let mut compiler = Self {
+            chunk: Chunk::default(),
+            name_indices: HashMap::new(),
+            locals: Vec::new(),
+            special_forms,
+            env,
+            unknown,
+        };
+
+        let span = nodes.last().map_or(Span::default(), |node| node.span);
+        compiler.block(nodes, span, true);
+        compiler.emit(Instr::Return, span);
+
+        compiler.chunk
+    }
+
+    /// Appends an instruction, returning its index
+    fn emit(&mut self, instr: Instr, span: Span) -> usize {
+        self.chunk.code.push(instr);
+        self.chunk.spans.push(span);
+        self.chunk.code.len() - 1
+    }
+
+    /// The index of the next instruction to be emitted
+    fn here(&self) -> u32 {
+        self.chunk.code.len() as u32
+    }
+
+    /// Sets the target of the jump at `at` to the next instruction
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        match &mut self.chunk.code[at] {
+            Instr::Jump(t)
+            | Instr::JumpIfFalse(t)
+            | Instr::PushLoop(t)
+            | Instr::MacroCheck { skip: t, .. }
+            | Instr::Guard { skip: t, .. } => *t = target,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Adds a value to the constant pool
+    fn constant(&mut self, value: Value) -> u32 {
+        self.chunk.constants.push(value);
+        self.chunk.constants.len() as u32 - 1
+    }
+
+    /// Adds a name to the name pool, reusing the existing entry if the
+    /// name has been added before
+    fn name(&mut self, name: &str) -> u32 {
+        if let Some(index) = self.name_indices.get(name) {
+            return *index;
+        }
+
+        let index = self.chunk.names.len() as u32;
+        self.chunk.names.push(name.into());
+        self.name_indices.insert(name.into(), index);
+        index
+    }
+
+    /// Returns the slot of the innermost local with a name, if there is one
+    fn local(&self, name: &str) -> Option<u16> {
+        self.locals
+            .iter()
+            .rev()
+            .find(|(local, _)| local == name)
+            .map(|(_, slot)| *slot)
+    }
+
+    /// Records the locals that are currently in scope, so that they can
+    /// be made visible to the tree-walking interpreter.
+    fn visible_locals(&mut self) -> u32 {
+        // Inner locals shadow outer ones with the same name, so only the
+        // innermost local with each name is kept.
+        let mut visible: Vec<(String, u16)> = Vec::new();
+        for (name, slot) in self.locals.iter().rev() {
+            if !visible.iter().any(|(n, _)| n == name) {
+                visible.push((name.clone(), *slot));
+            }
+        }
+
+        self.chunk.locals.push(visible);
+        self.chunk.locals.len() as u32 - 1
+    }
+
+    /// Compiles a node that the compiler can not lower, so that it is
+    /// evaluated by the tree-walking interpreter.
+    fn fallback(&mut self, node: &AstNode) {
+        self.chunk.nodes.push(node.clone());
+        let index = self.chunk.nodes.len() as u32 - 1;
+        let locals = self.visible_locals();
+        self.emit(Instr::Eval { node: index, locals }, node.span);
+    }
+
+    /// Emits a guard, which checks that names are still bound to what the
+    /// compiler assumed when the code runs, and evaluates `node` with the
+    /// tree-walker if they are not. Returns the index of the guard, which
+    /// has to be patched to skip the code that it guards.
+    fn guard(&mut self, assumptions: Vec<(&str, Assumption)>, node: &AstNode) -> usize {
+        let guard = assumptions
+            .into_iter()
+            .map(|(name, assumption)| (self.name(name), assumption))
+            .collect();
+        self.chunk.guards.push(guard);
+        self.chunk.nodes.push(node.clone());
+
+        let guard = self.chunk.guards.len() as u32 - 1;
+        let index = self.chunk.nodes.len() as u32 - 1;
+        let locals = self.visible_locals();
+        self.emit(Instr::Guard { guard, node: index, locals, skip: 0 }, node.span)
+    }
+
+    /// Turns the guard at `at`, which is the last instruction, into an
+    /// [`Instr::Eval`] of the node that it guards. This is used when the
+    /// code that it guards turns out not to be lowered.
+    fn unguard(&mut self, at: usize) {
+        self.chunk.guards.pop();
+        if let Instr::Guard { node, locals, .. } = self.chunk.code[at] {
+            self.chunk.code[at] = Instr::Eval { node, locals };
+        }
+    }
+
+    /// Returns the built-in macro of the special form that a name refers
+    /// to, if it can be lowered. Special forms are not lowered if their
+    /// name is a local, a parameter or bound by the code itself, or if it
+    /// is bound to anything other than the built-in macro.
+    fn special_form(&self, name: &str) -> Option<MacroFn> {
+        if !self.special_forms || self.local(name).is_some() || self.unknown.contains(name) {
+            return None;
+        }
+
+        let form = macros::special_form(name)?;
+        let value = self.env.borrow().get(name);
+        Assumption::Builtin(form).holds(value.as_ref()).then_some(form)
+    }
+
+    /// Compiles an expression, which leaves its value on the stack.
+    /// `tail` is whether the expression is in tail position.
+    fn expr(&mut self, node: &AstNode, tail: bool) {
+        grow_stack(|| self.node(node, tail))
+    }
+
+    fn node(&mut self, node: &AstNode, tail: bool) {
+        let span = node.span;
+
+        match &node.kind {
+            NodeKind::Int(i) => {
+                let index = self.constant(Value::Int(*i));
+                self.emit(Instr::Const(index), span);
+            }
+            NodeKind::BigInt(i) => {
+                let index = self.constant(Value::BigInt(i.clone()));
+                self.emit(Instr::Const(index), span);
+            }
+            NodeKind::Float(f) => {
+                let index = self.constant(Value::Float(*f));
+                self.emit(Instr::Const(index), span);
+            }
+            NodeKind::Str(s) => {
+                let index = self.constant(Value::Str(s.clone()));
+                self.emit(Instr::Const(index), span);
+            }
+            NodeKind::Symbol(s) => {
+                let index = self.constant(Value::Symbol(s.clone()));
+                self.emit(Instr::Const(index), span);
+            }
+            NodeKind::Operator(op) => {
+                let index = self.constant(Value::Operator(*op));
+                self.emit(Instr::Const(index), span);
+            }
+
+            NodeKind::Name(name) => match self.local(name) {
+                Some(slot) => {
+                    self.emit(Instr::LoadLocal(slot), span);
+                }
+                None => {
+                    let index = self.name(name);
+                    self.emit(Instr::LoadName(index), span);
+                }
+            },
+
+            NodeKind::List(elems) => {
+                let list = Value::List(elems.iter().map(to_value).collect());
+                let index = self.constant(list);
+                self.emit(Instr::Const(index), span);
+            }
+            NodeKind::Map(entries) => {
+                self.exprs(entries);
+                self.emit(Instr::MakeMap(entries.len() as u32 / 2), span);
+            }
+            NodeKind::Set(elems) => {
+                self.exprs(elems);
+                self.emit(Instr::MakeSet(elems.len() as u32), span);
+            }
+
+            NodeKind::Expr(nodes) => self.call(node, nodes, tail),
+        }
+    }
+
+    /// Compiles several expressions, leaving all of their values on the
+    /// stack.
+    fn exprs(&mut self, nodes: &[AstNode]) {
+        for node in nodes {
+            self.expr(node, false);
+        }
+    }
+
+    /// Compiles expressions in sequence, leaving only the value of the
+    /// last one on the stack. An empty block evaluates to null.
+    fn block(&mut self, nodes: &[AstNode], span: Span, tail: bool) {
+        if nodes.is_empty() {
+            let index = self.constant(Value::Null);
+            self.emit(Instr::Const(index), span);
+            return;
+        }
+
+        for (i, node) in nodes.iter().enumerate() {
+            if i > 0 {
+                self.emit(Instr::Pop, node.span);
+            }
+            self.expr(node, tail && i == nodes.len() - 1);
+        }
+    }
+
+    /// Compiles an expression like `(f a b)`. Special forms are compiled
+    /// into jumps, and anything else into a call.
+    fn call(&mut self, node: &AstNode, nodes: &[AstNode], tail: bool) {
+        let (head, args) = match nodes.split_first() {
+            Some(split) => split,
+            // Empty expressions are an error, which the tree-walking
+            // interpreter reports.
+            None => return self.fallback(node),
+        };
+
+        if let NodeKind::Name(name) = &head.kind {
+            // Method calls, like `(.read file 10)`
+            if name.len() > 1 && name.starts_with('.') {
+                self.exprs(args);
+                let name = self.name(name);
+                self.emit(Instr::CallMethod { argc: args.len() as u16, name }, node.span);
+                return;
+            }
+
+            if let Some(form) = self.special_form(name) {
+                let guard = self.guard(vec![(name, Assumption::Builtin(form))], node);
+                let lowered = match name.as_str() {
+                    "if" => self.if_else(node, args, tail),
+                    "block" => {
+                        self.block(args, node.span, tail);
+                        true
+                    }
+                    "while" => self.while_loop(node, args),
+                    "list" => {
+                        self.exprs(args);
+                        self.emit(Instr::MakeList(args.len() as u32), node.span);
+                        true
+                    }
+                    "let" => self.let_block(node, args, tail),
+                    _ => self.binding(name, node, args),
+                };
+
+                // Malformed forms are left to the tree-walker, which
+                // reports the error
+                match lowered {
+                    true => self.patch(guard),
+                    false => self.unguard(guard),
+                }
+                return;
+            }
+
+            if self.special_forms && self.local(name).is_none() && matches!(name.as_str(), "fn" | "defn") {
+                return self.fallback(node);
+            }
+
+            return self.function_call(node, head, args, tail);
+        }
+
+        // Operators can not be rebound, so they can be applied directly
+        if let NodeKind::Operator(op) = &head.kind {
+            self.exprs(args);
+            self.emit(Instr::CallOp { op: *op, argc: args.len() as u16 }, node.span);
+            return;
+        }
+
+        self.function_call(node, head, args, tail);
+    }
+
+    /// Compiles a call to a value, which may turn out to be a macro when
+    /// the code is run.
+    fn function_call(&mut self, node: &AstNode, head: &AstNode, args: &[AstNode], tail: bool) {
+        // Calls are named after the callee in backtraces. Callees which
+        // are not names are named when they are called.
+        let name = match &head.kind {
+            NodeKind::Name(name) => self.name(name),
+            _ => u32::MAX,
+        };
+
+        // Unbound global callees are left to the tree-walker, which may
+        // call a method with the name instead
+        match &head.kind {
+            NodeKind::Name(callee) if self.local(callee).is_none() => {
+                self.emit(Instr::LoadCallee(name), head.span);
+            }
+            _ => self.expr(head, false),
+        }
+
+        self.chunk.nodes.push(node.clone());
+        let index = self.chunk.nodes.len() as u32 - 1;
+        let locals = self.visible_locals();
+        let check = self.emit(Instr::MacroCheck { node: index, locals, skip: 0 }, node.span);
+
+        self.exprs(args);
+        let argc = args.len() as u16;
+        let call = match tail {
+            true => Instr::TailCall { argc, name },
+            false => Instr::Call { argc, name },
+        };
+        self.emit(call, node.span);
+        self.patch(check);
+    }
+
+    /// Compiles `(if cond then)` or `(if cond then else)`. Returns
+    /// `false` if the form is malformed.
+    fn if_else(&mut self, node: &AstNode, args: &[AstNode], tail: bool) -> bool {
+        if !matches!(args.len(), 2 | 3) {
+            return false;
+        }
+
+        self.expr(&args[0], false);
+        let to_else = self.emit(Instr::JumpIfFalse(0), node.span);
+
+        self.expr(&args[1], tail);
+        let to_end = self.emit(Instr::Jump(0), node.span);
+
+        self.patch(to_else);
+        match args.get(2) {
+            Some(else_expr) => self.expr(else_expr, tail),
+            None => self.block(&[], node.span, false),
+        }
+        self.patch(to_end);
+
+        true
+    }
+
+    /// Compiles `(while cond body...)`. The value of the loop is the value
+    /// of the last iteration's body, or null if the body never runs. A
+    /// `break` makes the loop evaluate to the value given to it instead.
+    /// Returns `false` if the form is malformed.
+    fn while_loop(&mut self, node: &AstNode, args: &[AstNode]) -> bool {
+        if args.is_empty() {
+            return false;
+        }
+
+        let push = self.emit(Instr::PushLoop(0), node.span);
+
+        // The result of the loop is kept on the stack, below the values
+        // used by each iteration. `continue` jumps back to here.
+        self.block(&[], node.span, false);
+
+        let start = self.here();
+        self.expr(&args[0], false);
+        let to_end = self.emit(Instr::JumpIfFalse(0), node.span);
+
+        // Like the `while` macro, each iteration evaluates the body as a
+        // block, in a fresh scope.
+        self.emit(Instr::Pop, node.span);
+        self.emit(Instr::PushScope, node.span);
+        self.block(&args[1..], node.span, false);
+        self.emit(Instr::PopScope, node.span);
+        self.emit(Instr::Jump(start), node.span);
+
+        self.patch(to_end);
+        self.patch(push);
+        self.emit(Instr::PopLoop, node.span);
+        true
+    }
+
+    /// Compiles `(set name value)`, `(define name value)` and
+    /// `(set! name value)`. Returns `false` if the form is malformed.
+    fn binding(&mut self, form: &str, node: &AstNode, args: &[AstNode]) -> bool {
+        let name = match args {
+            [AstNode { kind: NodeKind::Name(name), .. }, _] => name,
+            _ => return false,
+        };
+
+        self.expr(&args[1], false);
+
+        // Locals can only be updated, since defining a name always binds
+        // it in a scope.
+        if let (Some(slot), "set" | "set!") = (self.local(name), form) {
+            self.emit(Instr::StoreLocal(slot), node.span);
+            self.emit(Instr::LoadLocal(slot), node.span);
+            return true;
+        }
+
+        let index = self.name(name);
+        let instr = match form {
+            "set" => Instr::SetName(index),
+            "define" => Instr::DefineName(index),
+            _ => Instr::AssignName(index),
+        };
+        self.emit(instr, node.span);
+
+        true
+    }
+
+    /// Compiles `(let ((name value)...) body...)`. The bindings are stored
+    /// in local slots if neither their values nor the body bind any
+    /// names, and in a new scope otherwise. Returns `false` if the form is malformed.
+    /// 
+    /// Whether the callees bind names is decided by what they are bound
+    /// to when the code is compiled, so the local slots are guarded, and
+    /// the whole form is left to the tree-walker if any of them has been
+    /// rebound to a macro that may bind names by the time it runs.
+    fn let_block(&mut self, node: &AstNode, args: &[AstNode], tail: bool) -> bool {
+        let bindings = match args.first().map(|n| &n.kind) {
+            Some(NodeKind::Expr(bindings)) => bindings,
+            _ => return false,
+        };
+
+        let mut pairs = Vec::new();
+        for binding in bindings {
+            match &binding.kind {
+                NodeKind::Expr(pair) => match pair.as_slice() {
+                    [AstNode { kind: NodeKind::Name(name), .. }, value] => pairs.push((name, value)),
+                    _ => return false,
+                },
+                _ => return false,
+            }
+        }
+
+        let body = &args[1..];
+
+        let mut callees = Vec::new();
+        let values = pairs.iter().map(|(_, value)| *value);
+        if values.chain(body).any(|node| self.binds_names(node, &mut callees)) {
+            self.emit(Instr::PushScope, node.span);
+            for (name, value) in pairs {
+                self.expr(value, false);
+                let index = self.name(name);
+                self.emit(Instr::DefineName(index), node.span);
+                self.emit(Instr::Pop, node.span);
+            }
+            // A tail call leaves the scope when it replaces the current
+            // function's frame, so the scope does not have to be popped
+            // first.
+            self.block(body, node.span, tail);
+            self.emit(Instr::PopScope, node.span);
+
+            return true;
+        }
+
+        let guard = match callees.is_empty() {
+            true => None,
+            false => {
+                let assumptions = callees.iter().map(|name| (name.as_str(), Assumption::NoBindings)).collect();
+                Some(self.guard(assumptions, node))
+            }
+        };
+
+        // Bindings are evaluated in order, and each one is visible to
+        // the bindings after it.
+        let outer = self.locals.len();
+        for (name, value) in pairs {
+            self.expr(value, false);
+
+            let slot = self.chunk.num_locals;
+            self.chunk.num_locals += 1;
+            self.locals.push((name.clone(), slot));
+
+            self.emit(Instr::StoreLocal(slot), node.span);
+        }
+
+        self.block(body, node.span, tail);
+        self.locals.truncate(outer);
+        if let Some(guard) = guard {
+            self.patch(guard);
+        }
+
+        true
+    }
+
+    /// Returns `true` if a node contains a call that may bind names in
+    /// the current scope. The callees that were resolved to decide that
+    /// they do not are added to `callees`.
+    fn binds_names(&self, node: &AstNode, callees: &mut Vec<String>) -> bool {
+        match &node.kind {
+            NodeKind::Expr(nodes) => {
+                let is_binding = match nodes.first().map(|n| &n.kind) {
+                    Some(NodeKind::Name(name)) => self.may_bind(name, callees),
+                    Some(NodeKind::Operator(_)) | None => false,
+                    Some(_) => true,
+                };
+                is_binding || nodes.iter().any(|node| self.binds_names(node, callees))
+            }
+            NodeKind::List(nodes) | NodeKind::Map(nodes) | NodeKind::Set(nodes) => {
+                nodes.iter().any(|node| self.binds_names(node, callees))
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if a call to a name may bind names in the current
+    /// scope. Callees are resolved when the code is compiled. Only macros
+    /// can bind names, and the built-in ones that never do are trusted
+    /// not to. Callees that were resolved to something that does not
+    /// bind names are added to `callees`, so that they can be checked
+    /// again when the code runs.
+    fn may_bind(&self, name: &str, callees: &mut Vec<String>) -> bool {
+        // Method calls
+        if name.len() > 1 && name.starts_with('.') {
+            return false;
+        }
+
+        if self.local(name).is_some() || self.unknown.contains(name) {
+            return true;
+        }
+
+        let value = self.env.borrow().get(name);
+        if !Assumption::NoBindings.holds(value.as_ref()) {
+            return true;
+        }
+
+        if !callees.iter().any(|callee| callee == name) {
+            callees.push(name.into());
+        }
+        false
+    }
+}
+
+/// Adds the names that appear in a node anywhere other than at the
+/// head of an expression. These include every name that the code
+/// binds, like the names of `let` bindings and `define`s.
+fn operand_names(node: &AstNode, names: &mut HashSet<String>) {
+    match &node.kind {
+        NodeKind::Name(name) => {
+            names.insert(name.clone());
+        }
+        NodeKind::Expr(nodes) => {
+            let operands = match nodes.first().map(|n| &n.kind) {
+                Some(NodeKind::Name(_)) => &nodes[1..],
+                _ => nodes,
+            };
+            for node in operands {
+                operand_names(node, names);
+            }
+        }
+        NodeKind::List(nodes) | NodeKind::Map(nodes) | NodeKind::Set(nodes) => {
+            for node in nodes {
+                operand_names(node, names);
+            }
+        }
+        _ => (),
+    }
+}
@@ -0,0 +1,72 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::Value;
+
+/// A shared reference to a [`Scope`]. Functions hold on to the
+/// environment they were defined in, so scopes have to be
+/// reference counted.
+pub type Env = Rc<RefCell<Scope>>;
+
+/// A single frame of bindings. Lookups that fail in a scope are
+/// retried in its parent, until the outermost scope is reached.
+pub struct Scope {
+    /// The names bound in this scope.
+    vars: HashMap<String, Value>,
+    /// The enclosing scope, or `None` for the outermost scope.
+    parent: Option<Env>,
+}
+
+impl Scope {
+    /// Creates a new, empty scope nested inside `parent`.
+    pub fn new(parent: Option<Env>) -> Env {
+        Rc::new(RefCell::new(Self {
+            vars: HashMap::new(),
+            parent,
+        }))
+    }
+
+    /// Retrieves the value bound to a name, searching the enclosing
+    /// scopes if this scope does not contain it.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match self.vars.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref()?.borrow().get(name),
+        }
+    }
+
+    /// Returns the enclosing scope
+    pub fn parent(&self) -> Option<Env> {
+        self.parent.clone()
+    }
+
+    /// Returns every name that is visible from this scope.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.vars.keys().cloned().collect();
+        if let Some(parent) = &self.parent {
+            names.extend(parent.borrow().names());
+        }
+        names
+    }
+
+    /// Binds a name in this scope, shadowing any binding with the
+    /// same name in the enclosing scopes.
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.vars.insert(name.into(), value);
+    }
+
+    /// Updates the nearest existing binding of a name. Returns `false`
+    /// if the name is not bound in this scope or any enclosing scope.
+    pub fn assign(&mut self, name: &str, value: Value) -> bool {
+        if let Some(slot) = self.vars.get_mut(name) {
+            *slot = value;
+            return true;
+        }
+
+        match &self.parent {
+            Some(parent) => parent.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+}
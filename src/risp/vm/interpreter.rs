@@ -1,64 +1,327 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::rc::Rc;
 
-use super::{ErrorKind, RuntimeError, Value};
-use crate::risp::{shared::Op, AstNode, stdlib};
+use indexmap::{IndexMap, IndexSet};
+
+use super::{code, number, Control, Env, ErrorKind, InterpreterBuilder, IntoArgs, Lambda, Limits, NativeFn, RuntimeError, Scope, StackFrame, Value};
+use crate::risp::{shared::{grow_stack, Op}, to_ast, AstNode, Error, FileId, NodeKind, SourceMap, Span, SyntaxError};
 
 /// Used for conveniently creating [`RuntimeError`]s
 macro_rules! err {
     ($kind:ident, $msg:expr) => {
-        Err(RuntimeError {
-            kind: ErrorKind::$kind,
-            msg: $msg.into(),
-        })
+        Err(RuntimeError::new(ErrorKind::$kind, $msg).into())
     };
 }
 
+/// The type of the native functions that implement macros
+pub type MacroFn = fn(&mut Interpreter, &[AstNode]) -> Result<Value, Control>;
+
+/// A call to a function in tail position, which is made by the
+/// function's caller once the current function has returned. This
+/// keeps the stack from growing in recursive loops.
+struct TailCall {
+    func: Rc<Lambda>,
+    args: Vec<Value>,
+    /// The frame that replaces the current function's frame on the
+    /// call stack
+    frame: StackFrame,
+}
+
 /// A struct that interprets ASTs
 pub struct Interpreter {
-    symbols: HashMap<String, Value>,
+    /// The scope that names are currently resolved in.
+    pub(super) env: Env,
+    /// All of the source code that has been loaded into the interpreter.
+    sources: SourceMap,
+    /// Handles for the files loaded by `eval_str` and `read-string`.
+    /// Functions and errors with spans in one of these files hold a
+    /// clone of its handle, and the file is only reused once the
+    /// interpreter holds the last one.
+    loaded_files: Vec<Rc<FileId>>,
+    /// The functions and macros that are currently being called, with
+    /// the outermost call first.
+    pub(super) call_stack: Vec<StackFrame>,
+    /// Whether the macro that is being called is in tail position.
+    tail_position: bool,
+    /// A call in tail position that is waiting to be made.
+    tail_call: Option<TailCall>,
+    /// The limits on the resources that code can use.
+    pub(super) limits: Limits,
+    /// The number of evaluation steps taken since the limits were set.
+    pub(super) steps: u64,
+    /// The number of loops around the code being evaluated, within the
+    /// current function. `break` and `continue` can only be used if
+    /// this is not zero.
+    pub(crate) loops: usize,
+    /// The number of calls to functions defined in risp that are being
+    /// evaluated. `return` can only be used if this is not zero.
+    pub(crate) functions: usize,
+    /// The number of symbols created by `gensym`, which is used to make
+    /// each symbol unique.
+    pub(crate) gensyms: u64,
+    /// Whether the special forms like `if` were loaded, so that the
+    /// compiler can lower them into bytecode.
+    pub(super) special_forms: bool,
+    /// Where `print` and `println` write to. This is STDOUT by default.
+    pub(super) output: Box<dyn Write>,
+    /// Where `input` reads lines from, or `None` to read from STDIN.
+    pub(super) input: Option<Box<dyn BufRead>>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
-    /// Creates a new interpreter.
+    /// Creates a new interpreter, with the whole standard library
+    /// loaded and the default [`Limits`].
     pub fn new() -> Self {
-        // Create the interpreter's symbol table
-        let symbols: HashMap<String, Value> = {
-            let mut h = HashMap::new();
-            h.extend(stdlib::functions::SYMBOLS.clone().into_iter());
-            h.extend(stdlib::macros::SYMBOLS.clone().into_iter());
-            h.insert("true".into(), Value::Bool(true));
-            h.insert("false".into(), Value::Bool(false));
-            h
-        };
+        InterpreterBuilder::new().build()
+    }
 
+    /// Returns a builder for configuring a new interpreter.
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder::new()
+    }
+
+    /// Creates an interpreter whose global scope is `env`.
+    pub(super) fn with_globals(env: Env, limits: Limits) -> Self {
         Self {
-            symbols,
+            env,
+            sources: SourceMap::new(),
+            loaded_files: Vec::new(),
+            call_stack: Vec::new(),
+            tail_position: false,
+            tail_call: None,
+            limits,
+            steps: 0,
+            loops: 0,
+            functions: 0,
+            gensyms: 0,
+            special_forms: true,
+            output: Box::new(io::stdout()),
+            input: None,
+        }
+    }
+
+    /// Adds source code to the interpreter's [`SourceMap`] under a
+    /// name, and parses it. Errors produced by the resulting nodes can
+    /// be traced back to this source.
+    pub fn load(&mut self, name: &str, src: &str) -> Result<Vec<AstNode>, SyntaxError> {
+        let file = self.sources.add(name, src);
+        to_ast(src, file)
+    }
+
+    /// Like [`Interpreter::load`], but reuses a file that nothing holds
+    /// a handle for, if there is one. The file is not reused while the
+    /// returned handle, or a clone of it, exists.
+    pub(crate) fn load_spare(&mut self, name: &str, src: &str) -> (Rc<FileId>, Result<Vec<AstNode>, SyntaxError>) {
+        let file = match self.loaded_files.iter().find(|file| Rc::strong_count(file) == 1) {
+            Some(file) => {
+                self.sources.replace(**file, src);
+                file.clone()
+            }
+            None => {
+                let file = Rc::new(self.sources.add(name, src));
+                self.loaded_files.push(file.clone());
+                file
+            }
+        };
+        let nodes = to_ast(src, *file);
+        (file, nodes)
+    }
+
+    /// Returns handles for the files loaded by `eval_str` or
+    /// `read-string` that any of the spans point into, so that their
+    /// source is kept while the spans can still be reported.
+    pub(crate) fn hold_files(&self, spans: impl IntoIterator<Item = Span>) -> Vec<Rc<FileId>> {
+        let mut held: Vec<Rc<FileId>> = Vec::new();
+        for span in spans {
+            let file = self.loaded_files.iter().find(|file| ***file == span.file);
+            if let Some(file) = file.filter(|file| !held.iter().any(|h| Rc::ptr_eq(h, file))) {
+                held.push(file.clone());
+            }
         }
+        held
+    }
+
+    /// Makes an error that is returned to the embedder hold the files
+    /// that its span and backtrace point into.
+    fn hold_error_files(&self, mut err: RuntimeError) -> RuntimeError {
+        let spans = err.span.into_iter().chain(err.backtrace.iter().map(|frame| frame.span));
+        let files = self.hold_files(spans);
+        err.files.extend(files);
+        err
+    }
+
+    /// Parses and runs source code, returning the value of the last
+    /// expression, or null if there are none. The code is run with the
+    /// bytecode engine, and errors can be traced back to it through
+    /// [`Interpreter::sources`].
+    /// 
+    /// The source is kept while the functions it defines, or the errors
+    /// that point into it, still exist. After that, it is replaced by
+    /// the source of a later call.
+    pub fn eval_str(&mut self, src: &str) -> Result<Value, Error> {
+        let (file, nodes) = self.load_spare("<string>", src);
+
+        let result = nodes.map_err(Error::from).and_then(|nodes| {
+            let mut value = Value::Null;
+            for node in &nodes {
+                value = self.run(node)?;
+            }
+            Ok(value)
+        });
+
+        result.map_err(|err| match err {
+            Error::Syntax(mut err) => {
+                err.files.push(file);
+                Error::Syntax(err)
+            }
+            Error::Runtime(err) => Error::Runtime(self.hold_error_files(err)),
+        })
+    }
+
+    /// Returns the source code that has been loaded into the interpreter.
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    /// Sets where `print` and `println` write to, instead of STDOUT.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    /// Sets where `input` reads lines from, instead of STDIN.
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.input = Some(Box::new(input));
+    }
+
+    /// Returns the writer that `print` and `println` write to.
+    pub(crate) fn output(&mut self) -> &mut dyn Write {
+        &mut *self.output
+    }
+
+    /// Reads a line for `input`, including the newline, and appends it
+    /// to `buf`. Returns the number of bytes read, which is 0 at the end
+    /// of the input.
+    pub(crate) fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        match &mut self.input {
+            Some(input) => input.read_line(buf),
+            None => io::stdin().read_line(buf),
+        }
+    }
+
+    /// Returns the scope that names are currently resolved in.
+    pub fn env(&self) -> Env {
+        self.env.clone()
     }
 
     /// Retrieves the [`Value`] associated with a name in the interpreter's
     /// symbol table. Returns a [`RuntimeError`] if the name is not present
     /// in the symbol table.
     pub fn get_name(&self, name: &str) -> Result<Value, RuntimeError> {
-        match self.symbols.get(name) {
-            Some(value) => Ok(value.clone()),
-            None => err!(NameError, format!("{name} is not defined")),
+        match self.env.borrow().get(name) {
+            Some(value) => Ok(value),
+            None => Err(self.name_error(name)),
+        }
+    }
+
+    /// Creates a [`RuntimeError`] for a name that is not defined,
+    /// suggesting a similarly named symbol if one exists.
+    fn name_error(&self, name: &str) -> RuntimeError {
+        let error = RuntimeError::new(ErrorKind::NameError, format!("{name} is not defined"));
+
+        // Names within this distance are considered to be typos
+        let max_distance = (name.chars().count() / 3).max(1);
+
+        let suggestion = self
+            .env
+            .borrow()
+            .names()
+            .into_iter()
+            .map(|candidate| (edit_distance(name, &candidate), candidate))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min();
+
+        match suggestion {
+            Some((_, candidate)) => {
+                error.with_help(format!("a symbol with a similar name exists: `{candidate}`"))
+            }
+            None => error,
         }
     }
 
-    /// Creates an entry in the interpreter's symbol table associating
-    /// a name with a value. If an entry with the same name already
-    /// exists, then its value is updated.
+    /// Associates a name with a value. If the name is already bound in
+    /// the current scope or an enclosing one, the nearest binding is
+    /// updated. Otherwise, the name is defined in the current scope.
     pub fn set_name(&mut self, name: &str, value: Value) {
-        self.symbols.insert(name.into(), value);
+        let mut env = self.env.borrow_mut();
+        if !env.assign(name, value.clone()) {
+            env.define(name, value);
+        }
     }
 
-    /// Calls a native Rust function
-    pub fn call_rustfn(
+    /// Binds a name in the current scope, shadowing any binding with
+    /// the same name in the enclosing scopes.
+    pub fn define_name(&mut self, name: &str, value: Value) {
+        self.env.borrow_mut().define(name, value);
+    }
+
+    /// Updates the nearest binding of a name. Returns a [`RuntimeError`]
+    /// if the name is not bound in any scope.
+    pub fn assign_name(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+        if self.env.borrow_mut().assign(name, value) {
+            Ok(())
+        } else {
+            Err(self.name_error(name))
+        }
+    }
+
+    /// Runs a function inside a fresh scope nested in `parent`. The
+    /// previous scope is restored afterwards, even if the function
+    /// returns an error.
+    pub fn with_scope<T>(&mut self, parent: Env, func: impl FnOnce(&mut Self) -> T) -> T {
+        let scope = Scope::new(Some(parent));
+        let outer = mem::replace(&mut self.env, scope);
+        let result = func(self);
+        self.env = outer;
+
+        result
+    }
+
+    /// Runs a function with a frame pushed onto the call stack. If the
+    /// function fails, and the error does not have a backtrace yet, the
+    /// current call stack is attached to it.
+    pub(super) fn with_frame<T>(
         &mut self,
-        func: fn(&mut Interpreter, Vec<Value>) -> Result<Vec<Value>, RuntimeError>,
-        params: Vec<Value>,
-    ) -> Result<Value, RuntimeError> {
+        name: String,
+        span: Span,
+        func: impl FnOnce(&mut Self) -> Result<T, Control>,
+    ) -> Result<T, Control> {
+        self.check_depth()?;
+        self.call_stack.push(StackFrame { name, span });
+
+        let result = func(self).map_err(|control| match control {
+            Control::Error(mut err) => {
+                if err.backtrace.is_empty() {
+                    err.backtrace = self.call_stack.clone();
+                }
+                Control::Error(err)
+            }
+            signal => signal,
+        });
+
+        self.call_stack.pop();
+        result
+    }
+
+    /// Calls a native Rust function
+    pub fn call_rustfn(&mut self, func: &NativeFn, params: Vec<Value>) -> Result<Value, RuntimeError> {
         let result = func(self, params)?;
 
         // Returns Null if the function returns an empty Vec.
@@ -70,26 +333,186 @@ impl Interpreter {
             _ => Value::List(result),
         };
 
+        self.check_size(&result)?;
         Ok(result)
     }
 
+    /// Calls a function, native function or operator with arguments
+    /// that have already been evaluated.
+    pub(crate) fn call_value(&mut self, func: &Value, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        match func {
+            Value::RustFn(f) => self.call_rustfn(f.as_ref(), args),
+            Value::Lambda(f) => self.call_lambda(f, args),
+            Value::Operator(op) => self.call_operator(*op, args),
+            _ => err!(TypeError, format!("{} is not callable", func.type_name())),
+        }
+    }
+
+    /// Calls a function from Rust, like a function defined in a script
+    /// that was retrieved with [`Interpreter::get_fn`]. The arguments
+    /// can be [`Value`]s, or Rust values which are converted with
+    /// [`IntoValue`](super::IntoValue), such as `(1, "two")`.
+    pub fn call(&mut self, func: &Value, args: impl IntoArgs) -> Result<Value, Error> {
+        let result = self.call_value(func, args.into_args());
+        Ok(result.map_err(|err| self.hold_error_files(err))?)
+    }
+
+    /// Returns the function bound to a name, if the name is bound to
+    /// something that can be called.
+    pub fn get_fn(&self, name: &str) -> Option<Value> {
+        self.env.borrow().get(name).filter(Value::is_callable)
+    }
+
+    /// Calls a macro. `tail` is whether the macro call is in tail
+    /// position, which macros can check using
+    /// [`Interpreter::take_tail_position`].
+    pub(super) fn call_macro(
+        &mut self,
+        mac: MacroFn,
+        nodes: &[AstNode],
+        tail: bool,
+    ) -> Result<Value, Control> {
+        self.tail_position = tail;
+        let result = mac(self, nodes)?;
+        self.check_size(&result)?;
+        Ok(result)
+    }
+
+    /// Calls a macro with the unevaluated arguments of an expression.
+    /// `name` and `span` are used for the macro's frame on the call
+    /// stack.
+    /// 
+    /// Macros defined in risp return code, which is evaluated in place
+    /// of the call, outside of the macro's frame.
+    pub(super) fn apply_macro(
+        &mut self,
+        mac: &Value,
+        name: String,
+        span: Span,
+        nodes: &[AstNode],
+        tail: bool,
+    ) -> Result<Value, Control> {
+        match mac {
+            Value::RustMacro(f) => self.with_frame(name, span, |inter| inter.call_macro(*f, nodes, tail)),
+            Value::Macro(m) => {
+                let expansion = self.with_frame(name, span, |inter| Ok(inter.expand(m, nodes, span)?))?;
+                self.eval_tail(&expansion, tail)
+            }
+            _ => unreachable!("{} is not a macro", mac.type_name()),
+        }
+    }
+
+    /// Expands a call to a macro defined in risp, with the span of the
+    /// call. The arguments are converted to data, and the data that the
+    /// macro returns is converted back to code.
+    fn expand(&mut self, mac: &Rc<Lambda>, nodes: &[AstNode], span: Span) -> Result<AstNode, RuntimeError> {
+        let args = nodes.iter().map(code::to_value).collect();
+        let expansion = self.call_lambda(mac, args)?;
+        code::to_node(&expansion, span)
+    }
+
+    /// Returns `true` if the macro that is being called is in tail
+    /// position. Macros that evaluate an expression as their result,
+    /// like `if`, must call this before evaluating anything else, and
+    /// pass the result on to [`Interpreter::eval_tail`].
+    pub fn take_tail_position(&mut self) -> bool {
+        mem::take(&mut self.tail_position)
+    }
+
+    /// Calls a function defined in risp code.
+    ///
+    /// The arguments are bound to the function's parameters in a
+    /// fresh scope, nested inside the scope that the function was
+    /// defined in. The body is then evaluated in that scope, and the
+    /// value of its last expression is returned.
+    /// 
+    /// The last expression is in tail position. If it is a call to
+    /// another function, that function is called in a loop once this
+    /// one returns, so that recursive loops run in constant stack space.
+    /// 
+    /// The body can also return early with `return`.
+    pub fn call_lambda(&mut self, func: &Rc<Lambda>, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        // Loops around the call can not be broken out of from inside it
+        let loops = mem::take(&mut self.loops);
+        self.functions += 1;
+
+        let result = self.call_lambda_body(func, args);
+
+        self.functions -= 1;
+        self.loops = loops;
+        result
+    }
+
+    /// Evaluates the body of a function, and any calls that it makes in
+    /// tail position.
+    fn call_lambda_body(&mut self, func: &Rc<Lambda>, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let mut func = func.clone();
+        let mut args = args;
+
+        loop {
+            func.check_arity(args.len())?;
+
+            let result = self.with_scope(func.env.clone(), |inter| {
+                for (param, arg) in func.bind_args(args) {
+                    inter.define_name(param, arg);
+                }
+
+                let (last, init) = match func.body.split_last() {
+                    Some(split) => split,
+                    None => return Ok(Value::Null),
+                };
+                for node in init {
+                    inter.eval(node)?;
+                }
+
+                inter.eval_tail(last, true)
+            });
+
+            let result = match result {
+                Ok(value) | Err(Control::Return(value)) => value,
+                Err(control) => return Err(control.into_error()),
+            };
+
+            let call = match self.tail_call.take() {
+                Some(call) => call,
+                None => return Ok(result),
+            };
+
+            if let Some(frame) = self.call_stack.last_mut() {
+                *frame = call.frame;
+            }
+            func = call.func;
+            args = call.args;
+        }
+    }
+
     /// This method evaluates binary operators in a manner similar to
     /// `.reduce()`.
     /// 
     /// For example, `(+ 1 2 3 4)` is evaluated as `(+ (+ (+ 1 2) 3) 4)`
     /// which is `((1 + 2) + 3) + 4` in infix notation.
     /// 
-    /// Handling of boolean operators is delegated to the `call_boolean_op`
-    /// method.
+    /// Handling of comparison operators is delegated to the
+    /// `call_comparison` method. `-` with a single operand negates it.
     pub fn call_operator(
         &self,
         op: Op,
         operands: Vec<Value>,
     ) -> Result<Value, RuntimeError> {
 
-        // Boolean operators need different chaining logic
-        if let Op::Equal | Op::Greater | Op::Less = op {
-            return self.call_boolean_op(op, operands)
+        if let (Op::Minus, [operand]) = (op, operands.as_slice()) {
+            return match number::negate(operand) {
+                Some(value) => Ok(value),
+                None => err!(
+                    TypeError,
+                    format!("bad operand type for unary -: {}", operand.type_name())
+                ),
+            };
+        }
+
+        // Comparison operators need different chaining logic
+        if let Some(check) = op.comparison() {
+            return Ok(self.call_comparison(check, operands))
         }
 
         let mut params = operands.iter();
@@ -97,79 +520,139 @@ impl Interpreter {
         // Stores the left operand for each application of the operator.
         let mut left = match params.next() {
             Some(v) => v.clone(),
-            None => return err!(TypeError, "expected at least 1 argument"),
+            None => return Err(RuntimeError::arity(op.as_str(), 1, usize::MAX, 0)),
         };
 
         for right in params {
+            self.check_operands(&left, right, op)?;
             left = left.binary_op(right, &op)?;
         }
 
+        self.check_size(&left)?;
         Ok(left)
     }
 
-    /// Evaluates boolean operators.
+    /// Evaluates comparison operators, using the total ordering of
+    /// [`Value`]s. `check` decides whether an ordering satisfies the
+    /// comparison.
     /// 
-    /// Boolean operators have different chaining rules compared to
+    /// Comparison operators have different chaining rules compared to
     /// binary operators. `(< 1 2 3)` would be interpreted as
-    /// `(1 < 2) and (2 < 3)`.
-    fn call_boolean_op(
-        &self,
-        op: Op,
-        operands: Vec<Value>,
-    ) -> Result<Value, RuntimeError> {
-        let mut res = true;
+    /// `(1 < 2) and (2 < 3)`, and `(!= 1 2 1)` as `(1 != 2) and (2 != 1)`.
+    fn call_comparison(&self, check: fn(Ordering) -> bool, operands: Vec<Value>) -> Value {
+        let res = operands
+            .windows(2)
+            .all(|window| check(window[0].cmp(&window[1])));
 
-        for window in operands.windows(2) {
-            let left = &window[0];
-            let right = &window[1];
+        Value::Bool(res)
+    }
+    
+    /// Evaluates an AST node. If an error occurs, it is tagged with
+    /// the span of the innermost node that produced it.
+    /// 
+    /// Control flow signals, like `break`, are returned as they are,
+    /// so that macros which evaluate nodes can pass them on to the
+    /// enclosing loop or function.
+    pub fn eval(&mut self, node: &AstNode) -> Result<Value, Control> {
+        self.eval_tail(node, false)
+    }
 
-            if let Value::Bool(b) = left.binary_op(right, &op)? {
-                res = res && b;
+    /// Evaluates an AST node, which may be in tail position. If `tail`
+    /// is `true` and the node is a call to a function defined in risp,
+    /// the call is left for the enclosing [`Interpreter::call_lambda`]
+    /// to make, and null is returned in the meantime.
+    pub fn eval_tail(&mut self, node: &AstNode, tail: bool) -> Result<Value, Control> {
+        grow_stack(|| self.eval_node(node, tail)).map_err(|mut control| {
+            if let Control::Error(err) = &mut control {
+                err.span.get_or_insert(node.span);
             }
-        }
-        Ok(Value::Bool(res))
+            control
+        })
     }
-    
-    /// Evaluates an AST node
-    pub fn eval(&mut self, node: &AstNode) -> Result<Value, RuntimeError> {
-        match node {
+
+    fn eval_node(&mut self, node: &AstNode, tail: bool) -> Result<Value, Control> {
+        self.tick()?;
+
+        match &node.kind {
             // Names are evaluated by getting the value associated
             // with them.
-            AstNode::Name(name) => self.get_name(&name),
-            AstNode::Symbol(s) => Ok(Value::Symbol(s.clone())),
+            NodeKind::Name(name) => Ok(self.get_name(name)?),
+            NodeKind::Symbol(s) => Ok(Value::Symbol(s.clone())),
 
-            // Int, Float, Str, and Operator just involve transposing the
+            // Int, BigInt, Float, Str, and Operator just involve transposing the
             // inner content into a Value
-            AstNode::Int(num) => Ok(Value::Int(*num)),
-            AstNode::Float(f) => Ok(Value::Float(*f)),
-            AstNode::Str(s) => Ok(Value::Str(s.clone())),
-            AstNode::Operator(op) => Ok(Value::Operator(*op)),
-
-            AstNode::List(l) => {
-                let mut elems = Vec::new();
-                
-                for el in l {
-                   elems.push(self.eval(el)?)
+            NodeKind::Int(num) => Ok(Value::Int(*num)),
+            NodeKind::BigInt(num) => Ok(Value::BigInt(num.clone())),
+            NodeKind::Float(f) => Ok(Value::Float(*f)),
+            NodeKind::Str(s) => Ok(Value::Str(s.clone())),
+            NodeKind::Operator(op) => Ok(Value::Operator(*op)),
+
+            // Quoted lists are data, so their elements are not evaluated
+            NodeKind::List(l) => Ok(Value::List(l.iter().map(code::to_value).collect())),
+
+            NodeKind::Map(entries) => {
+                let mut map = IndexMap::new();
+
+                for pair in entries.chunks(2) {
+                    let key = self.eval(&pair[0])?;
+                    let value = self.eval(&pair[1])?;
+                    map.insert(key, value);
+                }
+
+                let map = Value::Map(map);
+                self.check_size(&map)?;
+                Ok(map)
+            }
+
+            NodeKind::Set(elems) => {
+                let mut set = IndexSet::new();
+
+                for el in elems {
+                    set.insert(self.eval(el)?);
                 }
 
-                return Ok(Value::List(elems))
-            },
+                let set = Value::Set(set);
+                self.check_size(&set)?;
+                Ok(set)
+            }
 
             // In expressions, the first item is the function to execute
             // And the rest of the items are the arguments
-            AstNode::Expr(nodes) => {
+            NodeKind::Expr(nodes) => {
                 if nodes.is_empty() {
                     return err!(ValueError, "expression is empty");
                 };
 
+                // Method calls, like `(.read file 10)`
+                if let NodeKind::Name(name) = &nodes[0].kind {
+                    if let Some(method) = name.strip_prefix('.').filter(|m| !m.is_empty()) {
+                        let mut args = Vec::new();
+                        for node in &nodes[1..] {
+                            args.push(self.eval(node)?);
+                        }
+                        return self.with_frame(name.clone(), node.span, |inter| {
+                            Ok(inter.call_method(method, args)?)
+                        });
+                    }
+                }
+
                 // Moves the function into a seperate variable
-                let func = &nodes[0];
-                let func = self.eval(func)?;
+                let func = match self.eval(&nodes[0]) {
+                    Ok(func) => func,
+                    Err(control) => return self.unbound_call(node, nodes, control),
+                };
+
+                // The name shown for this call in backtraces
+                let name = match (&nodes[0].kind, &func) {
+                    (NodeKind::Name(name), _) => name.clone(),
+                    (_, Value::Lambda(f)) => f.name.as_deref().unwrap_or("<lambda>").into(),
+                    (_, func) => func.repr(),
+                };
 
                 // Macros operate on AST nodes themselves, so they
                 // can be called immedicately
-                if let Value::RustMacro(mac) = func {
-                    return mac(self, &nodes[1..]);
+                if let Value::RustMacro(_) | Value::Macro(_) = func {
+                    return self.apply_macro(&func, name, node.span, &nodes[1..], tail);
                 }
 
                 // Evaluate each argument
@@ -178,13 +661,63 @@ impl Interpreter {
                     args.push(self.eval(node)?);
                 }
 
-                // Make sure the function is a callable
-                match func {
-                    Value::RustFn(f) => self.call_rustfn(f, args),
-                    Value::Operator(op) => self.call_operator(op, args),
-                    _ => err!(TypeError, format!("{} is not callable", func.type_name())),
+                if let (Value::Lambda(func), true) = (&func, tail) {
+                    func.check_arity(args.len())?;
+                    let frame = StackFrame { name, span: node.span };
+                    self.tail_call = Some(TailCall { func: func.clone(), args, frame });
+                    return Ok(Value::Null);
                 }
+
+                self.with_frame(name, node.span, |inter| Ok(inter.call_value(&func, args)?))
             }
         }
     }
+
+    /// Handles a call whose head could not be evaluated. If the head is
+    /// an unbound name, and the first argument is a name bound to a
+    /// native object, the head is called as a method of the object, like
+    /// `(read file 10)`. Otherwise, the original error is returned
+    /// without evaluating anything, so that a mistyped name does not run
+    /// the side effects of its arguments.
+    fn unbound_call(&mut self, node: &AstNode, nodes: &[AstNode], control: Control) -> Result<Value, Control> {
+        let (name, first) = match (&control, &nodes[0].kind, nodes.get(1).map(|n| &n.kind)) {
+            (Control::Error(err), NodeKind::Name(name), Some(NodeKind::Name(first)))
+                if matches!(err.kind, ErrorKind::NameError) =>
+            {
+                (name, first)
+            }
+            _ => return Err(control),
+        };
+
+        let object = match self.get_name(first) {
+            Ok(object @ Value::Native(_)) => object,
+            _ => return Err(control),
+        };
+
+        let mut args = vec![object];
+        for node in &nodes[2..] {
+            args.push(self.eval(node)?);
+        }
+        self.with_frame(name.clone(), node.span, |inter| Ok(inter.call_method(name, args)?))
+    }
+}
+
+/// Computes the Levenshtein distance between two strings, which is the
+/// number of single character edits needed to turn one into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + (ca != *cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
 }
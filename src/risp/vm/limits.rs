@@ -0,0 +1,174 @@
+//! Limits on the resources that risp code can use, so that untrusted
+//! code can not hang or crash the program running it.
+
+use super::{number, ErrorKind, Interpreter, RuntimeError, Value};
+use crate::risp::shared::Op;
+use num_traits::Signed;
+
+/// The default maximum depth of the call stack. The tree-walking
+/// interpreter uses the native stack for every call, and moves onto
+/// stacks allocated on the heap when it runs out, so this limits how
+/// much memory runaway recursion can use.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// The default maximum size of strings, collections and ints. This is
+/// far more than scripts usually need, but keeps them from trying to
+/// allocate more memory than the host has.
+pub const DEFAULT_MAX_SIZE: usize = 100_000_000;
+
+/// Limits on the resources that an [`Interpreter`] can use. Each limit
+/// can be turned off by setting it to `None`.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// The maximum number of frames on the call stack. Exceeding it
+    /// produces a `RecursionError`.
+    pub max_depth: Option<usize>,
+    /// The maximum number of evaluation steps. The tree-walker takes a
+    /// step for every node it evaluates, and the bytecode VM for every
+    /// instruction it executes. Exceeding it produces a `LimitExceeded`
+    /// error.
+    pub max_steps: Option<u64>,
+    /// The maximum length of a string, in bytes, or of a list, map or
+    /// set. Ints are also limited to this many bytes. Exceeding it
+    /// produces a `MemoryError`.
+    pub max_size: Option<usize>,
+}
+
+impl Default for Limits {
+    /// The depth of the call stack and the size of values are limited
+    /// by default, to prevent stack overflows and failed allocations.
+    /// The number of steps is not limited.
+    fn default() -> Self {
+        Self {
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_steps: None,
+            max_size: Some(DEFAULT_MAX_SIZE),
+        }
+    }
+}
+
+impl Interpreter {
+    /// Changes the limits that the interpreter enforces. The count of
+    /// evaluation steps is reset to zero.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.steps = 0;
+    }
+
+    /// Checks that another frame can be pushed onto the call stack.
+    pub(super) fn check_depth(&self) -> Result<(), RuntimeError> {
+        match self.limits.max_depth {
+            Some(max) if self.call_stack.len() >= max => Err(RuntimeError::new(
+                ErrorKind::RecursionError,
+                format!("maximum call depth of {max} exceeded"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts an evaluation step, checking that the step limit has not
+    /// been exceeded.
+    pub(crate) fn tick(&mut self) -> Result<(), RuntimeError> {
+        self.steps += 1;
+
+        match self.limits.max_steps {
+            Some(max) if self.steps > max => Err(RuntimeError::new(
+                ErrorKind::LimitExceeded,
+                format!("evaluation step limit of {max} exceeded"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Appends the values of an iterator to a list, like the ints of a
+    /// range. Every value takes a step, and the list is checked against
+    /// the size limit as it grows, so that long ranges stop at the
+    /// limits instead of using up memory.
+    pub(crate) fn extend_list(
+        &mut self,
+        list: &mut Vec<Value>,
+        values: impl Iterator<Item = Value>,
+    ) -> Result<(), RuntimeError> {
+        for value in values {
+            self.tick()?;
+            list.push(value);
+            self.check_len(list.len())?;
+        }
+        Ok(())
+    }
+
+    /// Checks that a string, collection or exact number is not larger
+    /// than the size limit. Other values always pass.
+    pub(super) fn check_size(&self, value: &Value) -> Result<(), RuntimeError> {
+        let len = match value {
+            Value::Str(s) => s.len(),
+            Value::List(l) => l.len(),
+            Value::Map(m) => m.len(),
+            Value::Set(s) => s.len(),
+            Value::BigInt(n) => usize::try_from(n.bits() / 8).unwrap_or(usize::MAX),
+            Value::Ratio(r) => {
+                let bits = r.numer().bits().saturating_add(r.denom().bits());
+                usize::try_from(bits / 8).unwrap_or(usize::MAX)
+            }
+            _ => return Ok(()),
+        };
+
+        self.check_len(len)
+    }
+
+    /// Checks that applying an operator to two values would not create
+    /// a string or int larger than the size limit. This has to be
+    /// checked before the operator is applied, since repeating a string,
+    /// raising an int to a power, or shifting it left can allocate far
+    /// more memory than the operands use.
+    pub(super) fn check_operands(&self, left: &Value, right: &Value, op: Op) -> Result<(), RuntimeError> {
+        match (left, right, op) {
+            (Value::Str(s), times, Op::Star) | (times, Value::Str(s), Op::Star) => {
+                let times = match times {
+                    Value::Int(n) => usize::try_from(*n).unwrap_or(0),
+                    Value::BigInt(n) if n.is_positive() => usize::MAX,
+                    _ => 0,
+                };
+                self.check_len(s.len().saturating_mul(times))
+            }
+
+            // The result has at least this many bits. Negative powers
+            // give ratios, whose denominators are just as large.
+            (base, Value::Int(exp), Op::Pow) => {
+                let bits = match base {
+                    // Both the numerator and the denominator are raised
+                    Value::Ratio(r) => Some(r.numer().bits().max(r.denom().bits())),
+                    base => number::bits(base),
+                };
+                match bits {
+                    Some(bits) if *exp != 0 => {
+                        self.check_bits(bits.saturating_sub(1).saturating_mul(exp.unsigned_abs()))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            (int, Value::Int(shift), Op::Shl) => match number::bits(int) {
+                Some(bits) if bits > 0 && *shift > 0 => {
+                    self.check_bits(bits.saturating_add(*shift as u64))
+                }
+                _ => Ok(()),
+            },
+
+            _ => Ok(()),
+        }
+    }
+
+    fn check_bits(&self, bits: u64) -> Result<(), RuntimeError> {
+        self.check_len(usize::try_from(bits / 8).unwrap_or(usize::MAX))
+    }
+
+    fn check_len(&self, len: usize) -> Result<(), RuntimeError> {
+        match self.limits.max_size {
+            Some(max) if len > max => Err(RuntimeError::new(
+                ErrorKind::MemoryError,
+                format!("size of {len} exceeds the limit of {max}"),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,420 @@
+//! The dispatch loop, which executes bytecode [`Chunk`]s.
+//! 
+//! Calls to functions defined in risp push a [`Frame`] instead of
+//! recursing, so deeply nested calls do not use up the native stack.
+//! Calls in tail position reuse the caller's frame instead, so recursive
+//! loops run in constant space. Function bodies are compiled the first
+//! time they are called from bytecode.
+//! 
+//! Control flow signals raised by macros, like `break`, unwind to the
+//! innermost [`Loop`] or [`Frame`].
+
+use std::mem;
+use std::rc::Rc;
+
+use indexmap::{IndexMap, IndexSet};
+
+use super::bytecode::{Chunk, Instr};
+use super::compiler::Compiler;
+use super::{Control, Env, Interpreter, RuntimeError, Scope, StackFrame, Value};
+use crate::risp::{shared::grow_stack, AstNode, NodeKind};
+
+/// The state of a function call that is waiting for a callee to return
+struct Frame {
+    chunk: Rc<Chunk>,
+    ip: usize,
+    locals: Vec<Value>,
+    /// The scope to restore when the callee returns
+    env: Env,
+    /// The loops that the call was made in
+    loops: Vec<Loop>,
+    /// The height of the stack when the function was called
+    base: usize,
+}
+
+/// A loop that is being executed, which `break` and `continue` unwind to
+struct Loop {
+    /// The instruction that starts each iteration
+    start: usize,
+    /// The instruction that ends the loop
+    end: usize,
+    /// The height of the stack when the loop started
+    stack: usize,
+    /// The scope that the loop started in
+    env: Env,
+}
+
+impl Interpreter {
+    /// Evaluates an AST node by compiling it to bytecode and executing
+    /// it. This produces the same results as [`Interpreter::eval`], but
+    /// is faster for code that loops.
+    pub fn run(&mut self, node: &AstNode) -> Result<Value, RuntimeError> {
+        let chunk = Compiler::compile(std::slice::from_ref(node), self.special_forms, self.env.clone(), &[]);
+        self.execute(Rc::new(chunk))
+    }
+
+    /// Executes a chunk. If an error occurs, the interpreter's scope,
+    /// call stack and loop counts are restored to what they were before
+    /// the chunk ran.
+    pub fn execute(&mut self, chunk: Rc<Chunk>) -> Result<Value, RuntimeError> {
+        let env = self.env.clone();
+        let depth = self.call_stack.len();
+        let (loops, functions) = (self.loops, self.functions);
+
+        let result = grow_stack(|| self.dispatch(chunk));
+
+        if result.is_err() {
+            self.env = env;
+            self.call_stack.truncate(depth);
+            self.loops = loops;
+            self.functions = functions;
+        }
+
+        result
+    }
+
+    /// Runs a function with some local slots bound to names in a new
+    /// scope, so that they are visible to the tree-walking interpreter.
+    /// Any changes made to the names are copied back to the slots.
+    fn with_locals<T>(
+        &mut self,
+        locals: &mut [Value],
+        visible: &[(String, u16)],
+        func: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        if visible.is_empty() {
+            return func(self);
+        }
+
+        let scope = Scope::new(Some(self.env.clone()));
+        for (name, slot) in visible {
+            scope.borrow_mut().define(name, locals[*slot as usize].clone());
+        }
+
+        let outer = mem::replace(&mut self.env, scope.clone());
+        let result = func(self);
+        self.env = outer;
+
+        for (name, slot) in visible {
+            if let Some(value) = scope.borrow().get(name) {
+                locals[*slot as usize] = value;
+            }
+        }
+
+        result
+    }
+
+    /// The main dispatch loop
+    fn dispatch(&mut self, chunk: Rc<Chunk>) -> Result<Value, RuntimeError> {
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut stack: Vec<Value> = Vec::new();
+        let mut loops: Vec<Loop> = Vec::new();
+        let mut base = 0;
+
+        let mut locals = vec![Value::Null; chunk.num_locals as usize];
+        let mut chunk = chunk;
+        let mut ip = 0;
+
+        loop {
+            let instr = chunk.code[ip];
+            let span = chunk.spans[ip];
+            ip += 1;
+
+            /// Unwraps a result, returning errors tagged with the span
+            /// and backtrace of the current instruction. Control flow
+            /// signals jump to the loop or function that they unwind to.
+            macro_rules! vm_try {
+                ($result:expr) => {
+                    match $result.map_err(Control::from) {
+                        Ok(value) => value,
+                        Err(Control::Break(value)) if !loops.is_empty() => {
+                            let innermost = loops.last().unwrap();
+                            stack.truncate(innermost.stack);
+                            stack.push(value);
+                            self.env = innermost.env.clone();
+                            ip = innermost.end;
+                            continue;
+                        }
+                        Err(Control::Continue) if !loops.is_empty() => {
+                            let innermost = loops.last().unwrap();
+                            stack.truncate(innermost.stack);
+                            self.env = innermost.env.clone();
+                            ip = innermost.start;
+                            continue;
+                        }
+                        // Every chunk ends with a `Return`, which leaves
+                        // the function.
+                        Err(Control::Return(value)) if !frames.is_empty() => {
+                            stack.truncate(base);
+                            stack.push(value);
+                            ip = chunk.code.len() - 1;
+                            continue;
+                        }
+                        Err(control) => {
+                            let mut err = control.into_error();
+                            err.span.get_or_insert(span);
+                            if err.backtrace.is_empty() {
+                                err.backtrace = self.call_stack.clone();
+                            }
+                            return Err(err);
+                        }
+                    }
+                };
+            }
+
+            vm_try!(self.tick());
+
+            match instr {
+                Instr::Const(index) => stack.push(chunk.constants[index as usize].clone()),
+                Instr::LoadName(index) => {
+                    stack.push(vm_try!(self.get_name(&chunk.names[index as usize])))
+                }
+                Instr::LoadCallee(index) => {
+                    let value = self.env.borrow().get(&chunk.names[index as usize]);
+                    if let Some(value) = value {
+                        stack.push(value);
+                        continue;
+                    }
+
+                    // The whole call is left to the tree-walker, which
+                    // reports the error or calls a method with the name
+                    let (node, visible, skip) = match chunk.code[ip] {
+                        Instr::MacroCheck { node, locals, skip } => (node, locals, skip),
+                        _ => unreachable!("a callee is always followed by a macro check"),
+                    };
+                    let visible = &chunk.locals[visible as usize];
+                    let node = &chunk.nodes[node as usize];
+                    let result = self.with_locals(&mut locals, visible, |inter| inter.eval(node));
+                    stack.push(vm_try!(result));
+                    ip = skip as usize;
+                }
+                Instr::LoadLocal(slot) => stack.push(locals[slot as usize].clone()),
+                Instr::StoreLocal(slot) => locals[slot as usize] = stack.pop().unwrap(),
+
+                Instr::SetName(index) => {
+                    let value = stack.last().unwrap().clone();
+                    self.set_name(&chunk.names[index as usize], value);
+                }
+                Instr::DefineName(index) => {
+                    let value = stack.last().unwrap().clone();
+                    self.define_name(&chunk.names[index as usize], value);
+                }
+                Instr::AssignName(index) => {
+                    let value = stack.last().unwrap().clone();
+                    vm_try!(self.assign_name(&chunk.names[index as usize], value));
+                }
+
+                Instr::Pop => {
+                    stack.pop();
+                }
+                Instr::Jump(target) => ip = target as usize,
+                Instr::JumpIfFalse(target) => {
+                    if !matches!(stack.pop(), Some(Value::Bool(true))) {
+                        ip = target as usize;
+                    }
+                }
+
+                Instr::PushLoop(end) => {
+                    loops.push(Loop {
+                        start: ip,
+                        end: end as usize,
+                        stack: stack.len(),
+                        env: self.env.clone(),
+                    });
+                    self.loops += 1;
+                }
+                Instr::PopLoop => {
+                    loops.pop();
+                    self.loops -= 1;
+                }
+
+                Instr::PushScope => self.env = Scope::new(Some(self.env.clone())),
+                Instr::PopScope => {
+                    let parent = self.env.borrow().parent();
+                    self.env = parent.expect("popped the outermost scope");
+                }
+
+                Instr::MacroCheck { node, locals: visible, skip } => {
+                    let mac = match stack.last() {
+                        Some(Value::RustMacro(_) | Value::Macro(_)) => stack.pop().unwrap(),
+                        _ => continue,
+                    };
+
+                    let nodes = match &chunk.nodes[node as usize].kind {
+                        NodeKind::Expr(nodes) => nodes,
+                        _ => unreachable!(),
+                    };
+                    let name = match &nodes[0].kind {
+                        NodeKind::Name(name) => name.clone(),
+                        _ => mac.repr(),
+                    };
+
+                    let visible = &chunk.locals[visible as usize];
+                    let result = self.with_locals(&mut locals, visible, |inter| {
+                        inter.apply_macro(&mac, name, span, &nodes[1..], false)
+                    });
+
+                    stack.push(vm_try!(result));
+                    ip = skip as usize;
+                }
+
+                Instr::Guard { guard, node, locals: visible, skip } => {
+                    let holds = chunk.guards[guard as usize].iter().all(|(name, assumption)| {
+                        let value = self.env.borrow().get(&chunk.names[*name as usize]);
+                        assumption.holds(value.as_ref())
+                    });
+                    if holds {
+                        continue;
+                    }
+
+                    let visible = &chunk.locals[visible as usize];
+                    let node = &chunk.nodes[node as usize];
+                    let result = self.with_locals(&mut locals, visible, |inter| inter.eval(node));
+                    stack.push(vm_try!(result));
+                    ip = skip as usize;
+                }
+
+                Instr::Call { argc, name } | Instr::TailCall { argc, name } => {
+                    let args = stack.split_off(stack.len() - argc as usize);
+                    let callee = stack.pop().unwrap();
+
+                    let name = match chunk.names.get(name as usize) {
+                        Some(name) => name.clone(),
+                        None => callee_name(&callee),
+                    };
+
+                    let func = match callee {
+                        Value::Lambda(func) => func,
+                        callee => {
+                            let result = self.with_frame(name, span, |inter| {
+                                Ok(inter.call_value(&callee, args)?)
+                            });
+                            stack.push(vm_try!(result));
+                            continue;
+                        }
+                    };
+
+                    // The outermost chunk is not a function body, so calls in
+                    // tail position there still have to return to it.
+                    let tail = matches!(instr, Instr::TailCall { .. }) && !frames.is_empty();
+                    let frame = StackFrame { name, span };
+                    match self.call_stack.last_mut() {
+                        Some(top) if tail => *top = frame,
+                        _ => {
+                            vm_try!(self.check_depth());
+                            self.call_stack.push(frame);
+                        }
+                    }
+                    vm_try!(func.check_arity(args.len()));
+
+                    let scope = Scope::new(Some(func.env.clone()));
+                    for (param, arg) in func.bind_args(args) {
+                        scope.borrow_mut().define(param, arg);
+                    }
+
+                    let body = func
+                        .chunk
+                        .get_or_init(|| {
+                            let params: Vec<String> = func.params.iter().chain(&func.rest).cloned().collect();
+                            Rc::new(Compiler::compile(&func.body, self.special_forms, func.env.clone(), &params))
+                        })
+                        .clone();
+                    let body_locals = vec![Value::Null; body.num_locals as usize];
+
+                    let caller = Frame {
+                        chunk: mem::replace(&mut chunk, body),
+                        ip,
+                        locals: mem::replace(&mut locals, body_locals),
+                        env: mem::replace(&mut self.env, scope),
+                        loops: mem::take(&mut loops),
+                        base: mem::replace(&mut base, stack.len()),
+                    };
+                    if !tail {
+                        frames.push(caller);
+                        self.functions += 1;
+                    }
+                    // Loops around the call can not be broken out of
+                    // from inside it
+                    self.loops = 0;
+                    ip = 0;
+                }
+
+                Instr::CallOp { op, argc } => {
+                    let args = stack.split_off(stack.len() - argc as usize);
+                    let result = self.with_frame(op.as_str().into(), span, |inter| {
+                        Ok(inter.call_operator(op, args)?)
+                    });
+                    stack.push(vm_try!(result));
+                }
+                Instr::CallMethod { argc, name } => {
+                    let args = stack.split_off(stack.len() - argc as usize);
+                    let name = &chunk.names[name as usize];
+                    let result = self.with_frame(name.clone(), span, |inter| {
+                        Ok(inter.call_method(&name[1..], args)?)
+                    });
+                    stack.push(vm_try!(result));
+                }
+
+                Instr::MakeList(len) => {
+                    let elems = stack.split_off(stack.len() - len as usize);
+                    let list = Value::List(elems);
+                    vm_try!(self.check_size(&list));
+                    stack.push(list);
+                }
+                Instr::MakeMap(len) => {
+                    let entries = stack.split_off(stack.len() - 2 * len as usize);
+                    let mut map = IndexMap::new();
+                    let mut entries = entries.into_iter();
+                    while let (Some(key), Some(value)) = (entries.next(), entries.next()) {
+                        map.insert(key, value);
+                    }
+                    let map = Value::Map(map);
+                    vm_try!(self.check_size(&map));
+                    stack.push(map);
+                }
+                Instr::MakeSet(len) => {
+                    let elems = stack.split_off(stack.len() - len as usize);
+                    let set = Value::Set(elems.into_iter().collect::<IndexSet<_>>());
+                    vm_try!(self.check_size(&set));
+                    stack.push(set);
+                }
+
+                Instr::Eval { node, locals: visible } => {
+                    let visible = &chunk.locals[visible as usize];
+                    let node = &chunk.nodes[node as usize];
+                    let result = self.with_locals(&mut locals, visible, |inter| inter.eval(node));
+                    stack.push(vm_try!(result));
+                }
+
+                Instr::Return => {
+                    let value = stack.pop().unwrap_or(Value::Null);
+
+                    let frame = match frames.pop() {
+                        Some(frame) => frame,
+                        None => return Ok(value),
+                    };
+
+                    chunk = frame.chunk;
+                    ip = frame.ip;
+                    locals = frame.locals;
+                    self.env = frame.env;
+                    self.loops = frame.loops.len();
+                    self.functions -= 1;
+                    loops = frame.loops;
+                    base = frame.base;
+                    self.call_stack.pop();
+
+                    stack.push(value);
+                }
+            }
+        }
+    }
+}
+
+/// The name shown in backtraces for a callee that is not called by name
+fn callee_name(callee: &Value) -> String {
+    match callee {
+        Value::Lambda(f) => f.name.as_deref().unwrap_or("<lambda>").into(),
+        other => other.repr(),
+    }
+}
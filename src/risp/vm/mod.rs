@@ -1,18 +1,44 @@
 //! This module evaluates ASTs generated by the parser. The main
 //! struct is the [`Interpreter`], which evaluates the AST nodes.
 //! The [`types`] module contains the different types that values
-//! can have.
+//! can have, and the [`env`] module contains the scopes that names
+//! are bound in.
+//! 
+//! There are two ways of evaluating ASTs. [`Interpreter::eval`] walks
+//! the tree directly. [`Interpreter::run`] uses the [`compiler`] to
+//! lower the tree into [`bytecode`], which is executed by the dispatch
+//! loop in [`machine`]. The tree-walker is the reference implementation,
+//! and the bytecode must produce the same results.
 //! 
 //! Evaluating an abstract syntax tree may produce a [`RuntimeError`]
 
 
+mod builder;
+mod bytecode;
+mod code;
+mod compiler;
+mod env;
 mod interpreter;
+mod limits;
+mod machine;
+mod native;
+mod object;
+mod number;
 mod types;
 
-use std::fmt;
+use std::{fmt, io};
+use std::rc::Rc;
+
+pub use builder::InterpreterBuilder;
+pub use env::{Env, Scope};
+pub use code::{to_node, to_value};
+pub use interpreter::{Interpreter, MacroFn};
+pub use limits::Limits;
+pub use native::{FromValue, IntoArgs, IntoResult, IntoValue, NativeFn, NativeFunction};
+pub use object::NativeObject;
+pub use types::{Lambda, Range, Value};
 
-pub use interpreter::Interpreter;
-pub use types::Value;
+use crate::risp::{FileId, Span};
 
 
 /// An enum used to discriminate between different kinds of errors.
@@ -21,16 +47,150 @@ pub enum ErrorKind {
     /// Thrown when a name is referenced but it does not exist in the
     /// interpreter's symbol table.
     NameError,
-    /// Thrown when a value is of the wrong type.
+    /// Thrown when a value is of the wrong type, or when a function
+    /// is called with the wrong number of arguments.
     TypeError,
     /// Thrown when a value supplied to a function is invalid.
     ValueError,
+    /// Thrown when a number is divided by zero.
+    ZeroDivisionError,
+    /// Thrown when the call stack grows deeper than the interpreter's
+    /// [`Limits`] allow.
+    RecursionError,
+    /// Thrown when code takes more evaluation steps than the
+    /// interpreter's [`Limits`] allow.
+    LimitExceeded,
+    /// Thrown when a string or collection grows larger than the
+    /// interpreter's [`Limits`] allow.
+    MemoryError,
+    /// Thrown by the `error` function, with a message supplied by the
+    /// code that raised it.
+    UserError,
+    /// Thrown by the `throw` function, which can raise any value. The
+    /// value is stored in the error's [`RuntimeError::value`].
+    Thrown,
+    /// Thrown when `break` or `continue` is used outside of a loop, or
+    /// `return` outside of a function.
+    ControlError,
+    /// Thrown when `read-string` is given code that can not be parsed.
+    SyntaxError,
+    /// Thrown when a method is called on a value that does not have it.
+    AttributeError,
+    /// Thrown when reading input or writing output fails.
+    IOError,
+}
+
+/// A single entry of the interpreter's call stack
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackFrame {
+    /// The name of the function or macro that was called
+    pub name: String,
+    /// The span of the expression that made the call
+    pub span: Span,
 }
 
 /// A struct that repesents an error produced while running the code
 pub struct RuntimeError {
     pub kind: ErrorKind,
     pub msg: String,
+    /// The span of the innermost node that was being evaluated when
+    /// the error occured. This is filled in by the interpreter.
+    pub span: Option<Span>,
+    /// An optional note on how the error might be fixed
+    pub help: Option<String>,
+    /// The call stack at the point where the error occured, with the
+    /// outermost call first. This is filled in by the interpreter.
+    pub backtrace: Vec<StackFrame>,
+    /// The value that was raised, if the error was raised by `throw`.
+    /// `catch` binds this value instead of describing the error.
+    pub value: Option<Box<Value>>,
+    /// Keeps the source that the error's spans point into from being
+    /// reused while the error exists.
+    pub(crate) files: Vec<Rc<FileId>>,
+}
+
+impl RuntimeError {
+    /// Creates a new runtime error, without a span
+    pub fn new(kind: ErrorKind, msg: impl Into<String>) -> Self {
+        Self {
+            kind,
+            msg: msg.into(),
+            span: None,
+            help: None,
+            backtrace: Vec::new(),
+            value: None,
+            files: Vec::new(),
+        }
+    }
+
+    /// Creates a `TypeError` for a call to `name` with the wrong number
+    /// of arguments. `min` and `max` are the numbers of arguments that
+    /// it accepts, where a `max` of `usize::MAX` means any number.
+    pub fn arity(name: &str, min: usize, max: usize, found: usize) -> Self {
+        let expected = if min == max {
+            min.to_string()
+        } else if max == usize::MAX {
+            format!("at least {min}")
+        } else {
+            format!("{min} to {max}")
+        };
+
+        // Like "1 argument" or "at least 1 argument", but "0 to 1 arguments"
+        let noun = match (min, max) {
+            (1, 1) | (1, usize::MAX) => "argument",
+            _ => "arguments",
+        };
+
+        let msg = format!("{name} expected {expected} {noun}, found {found}");
+        Self::new(ErrorKind::TypeError, msg)
+    }
+
+    /// Attaches a help message to the error
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+/// The reason that evaluation stopped before producing a value. This is
+/// either an error, or a control flow signal which unwinds to the
+/// nearest loop or function.
+pub enum Control {
+    Error(RuntimeError),
+    /// Leaves the nearest loop, which evaluates to the value
+    Break(Value),
+    /// Skips to the next iteration of the nearest loop
+    Continue,
+    /// Returns the value from the nearest function
+    Return(Value),
+}
+
+impl Control {
+    /// Converts the control flow into an error. This is used when a
+    /// signal reaches a point that it can not unwind past, such as the
+    /// top level of the program.
+    pub fn into_error(self) -> RuntimeError {
+        let msg = match self {
+            Control::Error(err) => return err,
+            Control::Break(_) => "break outside of a loop",
+            Control::Continue => "continue outside of a loop",
+            Control::Return(_) => "return outside of a function",
+        };
+
+        RuntimeError::new(ErrorKind::ControlError, msg)
+    }
+}
+
+impl From<io::Error> for RuntimeError {
+    fn from(err: io::Error) -> Self {
+        RuntimeError::new(ErrorKind::IOError, err.to_string())
+    }
+}
+
+impl From<RuntimeError> for Control {
+    fn from(err: RuntimeError) -> Self {
+        Control::Error(err)
+    }
 }
 
 impl fmt::Debug for RuntimeError {
@@ -38,3 +198,11 @@ impl fmt::Debug for RuntimeError {
         write!(f, "{:?}: {}", self.kind, self.msg)
     }
 }
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.msg)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
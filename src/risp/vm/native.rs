@@ -0,0 +1,362 @@
+//! Native functions, which are written in Rust and called from risp.
+//!
+//! The standard library implements its functions as [`NativeFn`]s,
+//! which receive their arguments as [`Value`]s. Host programs can
+//! instead register ordinary Rust closures with
+//! [`Interpreter::register_fn`]. Their arguments are converted with
+//! [`FromValue`], and their results with [`IntoValue`], so that
+//! arity and type errors are reported automatically:
+//!
+//! ```
+//! use risp::Interpreter;
+//!
+//! let mut interpreter = Interpreter::new();
+//! let greeting = String::from("Hello");
+//! interpreter.register_fn("greet", move |name: String, times: i64| {
+//!     vec![format!("{greeting}, {name}!"); times as usize]
+//! });
+//!
+//! let value = interpreter.eval_str(r#"(greet "risp" 2)"#).unwrap();
+//! assert_eq!(value.repr(), r#"["Hello, risp!" "Hello, risp!"]"#);
+//!
+//! let err = interpreter.eval_str(r#"(greet "risp" "twice")"#).unwrap_err();
+//! assert_eq!(err.to_string(), "TypeError: greet expected an int, found str");
+//! ```
+
+use std::rc::Rc;
+
+use num_bigint::BigInt;
+
+use super::{number, ErrorKind, Interpreter, RuntimeError, Value};
+
+/// The type of native functions. They receive the interpreter and their
+/// evaluated arguments. Returning no values produces null, and
+/// returning several values produces a list of them.
+pub type NativeFn = dyn Fn(&mut Interpreter, Vec<Value>) -> Result<Vec<Value>, RuntimeError>;
+
+/// A Rust type which can be converted from a risp [`Value`], so that
+/// it can be used as an argument of a function registered with
+/// [`Interpreter::register_fn`].
+pub trait FromValue: Sized {
+    /// Converts a value, returning `None` if it has the wrong type.
+    fn from_value(value: &Value) -> Option<Self>;
+
+    /// Describes the values that can be converted, like `an int`. This
+    /// is used in the messages of type errors.
+    fn expected() -> String;
+}
+
+/// A Rust type which can be converted into a risp [`Value`], so that
+/// it can be returned from a function registered with
+/// [`Interpreter::register_fn`].
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+/// The result of a function registered with
+/// [`Interpreter::register_fn`]. This is either a value, or a `Result`
+/// whose errors are converted into [`RuntimeError`]s.
+pub trait IntoResult {
+    fn into_result(self) -> Result<Value, RuntimeError>;
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+
+    fn expected() -> String {
+        "any value".into()
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn expected() -> String {
+        "an int".into()
+    }
+}
+
+impl FromValue for BigInt {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Int(i) => Some(BigInt::from(*i)),
+            Value::BigInt(i) => Some(i.clone()),
+            _ => None,
+        }
+    }
+
+    fn expected() -> String {
+        "an int".into()
+    }
+}
+
+/// Floats also accept ints, which are converted to the nearest float
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Float(f) => Some(*f),
+            Value::Int(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    fn expected() -> String {
+        "a number".into()
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    fn expected() -> String {
+        "a bool".into()
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Str(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    fn expected() -> String {
+        "a str".into()
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::List(items) => items.iter().map(T::from_value).collect(),
+            _ => None,
+        }
+    }
+
+    fn expected() -> String {
+        format!("a list of {}", plural(&T::expected()))
+    }
+}
+
+/// Null is converted to `None`
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+
+    fn expected() -> String {
+        format!("{} or null", T::expected())
+    }
+}
+
+/// Turns a description like `an int` into `ints`
+fn plural(expected: &str) -> String {
+    let noun = expected
+        .strip_prefix("an ")
+        .or_else(|| expected.strip_prefix("a "))
+        .unwrap_or(expected);
+    format!("{noun}s")
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+/// The unit type is converted to null
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Null
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::Int(self)
+    }
+}
+
+impl IntoValue for i32 {
+    fn into_value(self) -> Value {
+        Value::Int(self.into())
+    }
+}
+
+impl IntoValue for usize {
+    fn into_value(self) -> Value {
+        number::normalize_int(BigInt::from(self))
+    }
+}
+
+impl IntoValue for BigInt {
+    fn into_value(self) -> Value {
+        number::normalize_int(self)
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::Str(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::Str(self.into())
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::List(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+/// `None` is converted to null
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        self.map_or(Value::Null, IntoValue::into_value)
+    }
+}
+
+impl<T: IntoValue> IntoResult for T {
+    fn into_result(self) -> Result<Value, RuntimeError> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue, E: Into<RuntimeError>> IntoResult for Result<T, E> {
+    fn into_result(self) -> Result<Value, RuntimeError> {
+        self.map(IntoValue::into_value).map_err(Into::into)
+    }
+}
+
+/// The arguments of a call to a risp function from Rust, which is made
+/// with [`Interpreter::call`]. This is implemented for `Vec`s and
+/// arrays of values, and for tuples of up to 6 values, which may have
+/// different types.
+pub trait IntoArgs {
+    fn into_args(self) -> Vec<Value>;
+}
+
+impl<T: IntoValue> IntoArgs for Vec<T> {
+    fn into_args(self) -> Vec<Value> {
+        self.into_iter().map(IntoValue::into_value).collect()
+    }
+}
+
+impl<T: IntoValue, const N: usize> IntoArgs for [T; N] {
+    fn into_args(self) -> Vec<Value> {
+        self.into_iter().map(IntoValue::into_value).collect()
+    }
+}
+
+macro_rules! impl_into_args {
+    ($($arg:ident $value:ident),*) => {
+        impl<$($arg: IntoValue),*> IntoArgs for ($($arg,)*) {
+            fn into_args(self) -> Vec<Value> {
+                let ($($value,)*) = self;
+                vec![$($value.into_value()),*]
+            }
+        }
+    };
+}
+
+impl_into_args!();
+impl_into_args!(A a);
+impl_into_args!(A a, B b);
+impl_into_args!(A a, B b, C c);
+impl_into_args!(A a, B b, C c, D d);
+impl_into_args!(A a, B b, C c, D d, E e);
+impl_into_args!(A a, B b, C c, D d, E e, F f);
+
+/// A Rust function which can be called from risp. This is implemented
+/// for closures with up to 6 arguments which implement [`FromValue`],
+/// and whose result implements [`IntoResult`]. `Args` is the tuple of
+/// the argument types.
+pub trait NativeFunction<Args> {
+    /// Wraps the function as a [`NativeFn`], which checks and converts
+    /// its arguments. `name` is used in the messages of errors.
+    fn into_native(self, name: &str) -> Rc<NativeFn>;
+}
+
+/// Converts an argument of a native function
+fn convert<T: FromValue>(name: &str, value: &Value) -> Result<T, RuntimeError> {
+    T::from_value(value).ok_or_else(|| {
+        let msg = format!("{name} expected {}, found {}", T::expected(), value.type_name());
+        RuntimeError::new(ErrorKind::TypeError, msg)
+    })
+}
+
+macro_rules! impl_native_function {
+    ($($arg:ident $value:ident),*) => {
+        impl<Func, Ret, $($arg),*> NativeFunction<($($arg,)*)> for Func
+        where
+            Func: Fn($($arg),*) -> Ret + 'static,
+            Ret: IntoResult,
+            $($arg: FromValue,)*
+        {
+            fn into_native(self, name: &str) -> Rc<NativeFn> {
+                const ARITY: usize = 0 $(+ { stringify!($value); 1 })*;
+                let name = name.to_string();
+
+                Rc::new(move |_, args| {
+                    let [$($value),*] = match <[Value; ARITY]>::try_from(args) {
+                        Ok(args) => args,
+                        Err(args) => return Err(RuntimeError::arity(&name, ARITY, ARITY, args.len())),
+                    };
+
+                    let result = self($(convert::<$arg>(&name, &$value)?),*);
+                    Ok(vec![result.into_result()?])
+                })
+            }
+        }
+    };
+}
+
+impl_native_function!();
+impl_native_function!(A a);
+impl_native_function!(A a, B b);
+impl_native_function!(A a, B b, C c);
+impl_native_function!(A a, B b, C c, D d);
+impl_native_function!(A a, B b, C c, D d, E e);
+impl_native_function!(A a, B b, C c, D d, E e, F f);
+
+impl Interpreter {
+    /// Binds a Rust closure to a name in the current scope, so that it
+    /// can be called from risp. The closure may capture state, and its
+    /// arguments and result are converted automatically.
+    pub fn register_fn<Args>(&mut self, name: &str, func: impl NativeFunction<Args>) {
+        let native = func.into_native(name);
+        self.define_name(name, Value::RustFn(native));
+    }
+}
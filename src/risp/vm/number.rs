@@ -0,0 +1,374 @@
+//! Arithmetic and comparisons on numbers.
+//!
+//! Numbers form a tower: ints, ratios and floats. Ints are 64 bits, and
+//! are promoted to big ints when an operation overflows. Dividing ints
+//! gives an exact ratio when the result is not whole. Floats are
+//! contagious, so any operation involving a float gives a float.
+//!
+//! Exact results are always normalized: big ints that fit in 64 bits
+//! become ints, and ratios with a denominator of 1 become ints. This
+//! means every exact number has exactly one representation.
+//!
+//! Bitwise operators only apply to ints, and treat them as if they were
+//! stored in two's complement with infinitely many bits.
+
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+use super::{ErrorKind, RuntimeError, Value};
+use crate::risp::shared::Op;
+
+/// A number converted to a level of the tower, so that it can be
+/// combined with another number at the same level.
+enum Level {
+    Int(BigInt),
+    Ratio(BigRational),
+    Float(f64),
+}
+
+impl Level {
+    /// Converts a value to the lowest level that can represent it.
+    /// Returns `None` if the value is not a number.
+    fn of(value: &Value) -> Option<Self> {
+        let level = match value {
+            Value::Int(a) => Level::Int(BigInt::from(*a)),
+            Value::BigInt(a) => Level::Int(a.clone()),
+            Value::Ratio(a) => Level::Ratio(a.clone()),
+            Value::Float(a) => Level::Float(*a),
+            _ => return None,
+        };
+        Some(level)
+    }
+
+    /// The height of the level in the tower
+    fn height(&self) -> u8 {
+        match self {
+            Level::Int(_) => 0,
+            Level::Ratio(_) => 1,
+            Level::Float(_) => 2,
+        }
+    }
+
+    /// Raises the number to a level of the tower. Numbers are never
+    /// lowered.
+    fn raise(self, height: u8) -> Self {
+        match (self, height) {
+            (Level::Int(a), 1) => Level::Ratio(BigRational::from_integer(a)),
+            (Level::Int(a), 2) => Level::Float(a.to_f64().unwrap_or(f64::NAN)),
+            (Level::Ratio(a), 2) => Level::Float(a.to_f64().unwrap_or(f64::NAN)),
+            (level, _) => level,
+        }
+    }
+}
+
+/// Creates the error for dividing by zero
+fn zero_division() -> RuntimeError {
+    RuntimeError::new(ErrorKind::ZeroDivisionError, "division by zero")
+}
+
+/// Converts a big int to a value, using an int if it fits
+pub fn normalize_int(a: BigInt) -> Value {
+    match a.to_i64() {
+        Some(a) => Value::Int(a),
+        None => Value::BigInt(a),
+    }
+}
+
+/// Converts a ratio to a value, using an int if it is whole
+pub fn normalize_ratio(a: BigRational) -> Value {
+    if a.is_integer() {
+        normalize_int(a.to_integer())
+    } else {
+        Value::Ratio(a)
+    }
+}
+
+/// Creates the error for a result that would use too much memory
+fn too_large() -> RuntimeError {
+    RuntimeError::new(ErrorKind::MemoryError, "result is too large")
+}
+
+/// Applies an arithmetic operator to two ints, without promoting them.
+/// Returns `None` if the result does not fit in an int.
+fn int_op(op: Op, a: i64, b: i64) -> Option<Result<Value, RuntimeError>> {
+    let divides = matches!(op, Op::Slash | Op::Mod | Op::Rem | Op::Quot);
+    if divides && b == 0 {
+        return Some(Err(zero_division()));
+    }
+
+    let result = match op {
+        Op::Plus => a.checked_add(b)?,
+        Op::Minus => a.checked_sub(b)?,
+        Op::Star => a.checked_mul(b)?,
+        Op::Slash if a.checked_rem(b)? == 0 => a.checked_div(b)?,
+        Op::Rem => a.checked_rem(b)?,
+        Op::Quot => a.checked_div(b)?,
+        Op::Mod => {
+            let rem = a.checked_rem(b)?;
+            if rem != 0 && (rem < 0) != (b < 0) {
+                rem + b
+            } else {
+                rem
+            }
+        }
+        Op::Pow => a.checked_pow(u32::try_from(b).ok()?)?,
+        _ => return None,
+    };
+    Some(Ok(Value::Int(result)))
+}
+
+/// Raises an exact number to an int power, giving an exact result.
+fn exact_pow(base: BigRational, exp: &BigInt) -> Result<Value, RuntimeError> {
+    let exp_u32 = match exp.abs().to_u32() {
+        Some(exp) => exp,
+        // Only 0, 1 and -1 can be raised to huge powers
+        None if base.is_zero() && exp.is_negative() => return Err(zero_division()),
+        None if base.is_zero() => return Ok(Value::Int(0)),
+        None if base.abs().is_one() => {
+            // Even powers of -1 are 1
+            let base = if exp.bit(0) { base } else { base.abs() };
+            return Ok(normalize_ratio(base));
+        }
+        None => return Err(too_large()),
+    };
+
+    if exp.is_negative() && base.is_zero() {
+        return Err(zero_division());
+    }
+
+    let numer = base.numer().pow(exp_u32);
+    let denom = base.denom().pow(exp_u32);
+    let result = match exp.is_negative() {
+        true => BigRational::new(denom, numer),
+        false => BigRational::new(numer, denom),
+    };
+    Ok(normalize_ratio(result))
+}
+
+/// Applies an arithmetic operator to two numbers. Bools are treated as
+/// the ints 0 and 1. Returns `None` if either value is not a number, or
+/// if the operator is not an arithmetic operator.
+pub fn arith(op: Op, a: &Value, b: &Value) -> Option<Result<Value, RuntimeError>> {
+    use Op::*;
+    if !matches!(op, Plus | Minus | Star | Slash | Mod | Rem | Quot | Pow) {
+        return None;
+    }
+
+    // Bools only act as numbers alongside other numbers
+    let (a, b) = match (a, b) {
+        (Value::Bool(a), b) if b.is_number() => (&Value::Int(*a as i64), b),
+        (a, Value::Bool(b)) if a.is_number() => (a, &Value::Int(*b as i64)),
+        _ => (a, b),
+    };
+
+    // Ints that do not overflow are by far the most common case
+    if let (Value::Int(a), Value::Int(b)) = (a, b) {
+        if let Some(result) = int_op(op, *a, *b) {
+            return Some(result);
+        }
+    }
+
+    let (a, b) = (Level::of(a)?, Level::of(b)?);
+
+    // Exact numbers raised to int powers stay exact. Any other powers
+    // are computed with floats.
+    if op == Pow {
+        return match (a.raise(1), b) {
+            (Level::Ratio(a), Level::Int(b)) => Some(exact_pow(a, &b)),
+            (a, b) => match (a.raise(2), b.raise(2)) {
+                (Level::Float(a), Level::Float(b)) => Some(Ok(Value::Float(a.powf(b)))),
+                _ => unreachable!("numbers are raised to floats"),
+            },
+        };
+    }
+
+    let height = a.height().max(b.height());
+    let result = match (a.raise(height), b.raise(height)) {
+        (Level::Int(a), Level::Int(b)) => match op {
+            Plus => normalize_int(a + b),
+            Minus => normalize_int(a - b),
+            Star => normalize_int(a * b),
+            _ if b.is_zero() => return Some(Err(zero_division())),
+            Slash => normalize_ratio(BigRational::new(a, b)),
+            // Dividing big ints truncates, and the remainder has the
+            // sign of the dividend
+            Quot => normalize_int(a / b),
+            Rem => normalize_int(a % b),
+            _ => {
+                let rem = &a % &b;
+                if !rem.is_zero() && rem.is_negative() != b.is_negative() {
+                    normalize_int(rem + b)
+                } else {
+                    normalize_int(rem)
+                }
+            }
+        },
+        (Level::Ratio(a), Level::Ratio(b)) => match op {
+            Plus => normalize_ratio(a + b),
+            Minus => normalize_ratio(a - b),
+            Star => normalize_ratio(a * b),
+            _ if b.is_zero() => return Some(Err(zero_division())),
+            Slash => normalize_ratio(a / b),
+            Quot => normalize_ratio((a / b).trunc()),
+            Rem => normalize_ratio(&a - &b * (&a / &b).trunc()),
+            _ => normalize_ratio(&a - &b * (&a / &b).floor()),
+        },
+        (Level::Float(a), Level::Float(b)) => match op {
+            Plus => Value::Float(a + b),
+            Minus => Value::Float(a - b),
+            Star => Value::Float(a * b),
+            _ if b == 0.0 => return Some(Err(zero_division())),
+            Slash => Value::Float(a / b),
+            Quot => Value::Float((a / b).trunc()),
+            Rem => Value::Float(a % b),
+            _ => {
+                let rem = a % b;
+                if rem != 0.0 && (rem < 0.0) != (b < 0.0) {
+                    Value::Float(rem + b)
+                } else {
+                    Value::Float(rem)
+                }
+            }
+        },
+        _ => unreachable!("numbers are raised to the same level"),
+    };
+
+    Some(Ok(result))
+}
+
+/// Converts an int value to a big int. Returns `None` if the value is
+/// not an int.
+fn big_int(a: &Value) -> Option<BigInt> {
+    match a {
+        Value::Int(a) => Some(BigInt::from(*a)),
+        Value::BigInt(a) => Some(a.clone()),
+        _ => None,
+    }
+}
+
+/// Applies a bitwise operator to two ints. Returns `None` if either
+/// value is not an int, or if the operator is not a bitwise operator.
+pub fn bitwise(op: Op, a: &Value, b: &Value) -> Option<Result<Value, RuntimeError>> {
+    use Op::*;
+    if !matches!(op, BitAnd | BitOr | BitXor | Shl | Shr) {
+        return None;
+    }
+
+    if let (Value::Int(a), Value::Int(b)) = (a, b) {
+        let result = match op {
+            BitAnd => Some(a & b),
+            BitOr => Some(a | b),
+            BitXor => Some(a ^ b),
+            Shl => u32::try_from(*b).ok().and_then(|n| a.checked_shl(n).filter(|r| r >> n == *a)),
+            // Shifting right by 64 or more bits leaves only the sign
+            Shr => u32::try_from(*b).ok().map(|b| a >> b.min(63)),
+            _ => None,
+        };
+        if let Some(result) = result {
+            return Some(Ok(Value::Int(result)));
+        }
+    }
+
+    let (a, b) = (big_int(a)?, big_int(b)?);
+    if matches!(op, Shl | Shr) && b.is_negative() {
+        let msg = "shift amount must not be negative";
+        return Some(Err(RuntimeError::new(ErrorKind::ValueError, msg)));
+    }
+
+    let result = match op {
+        BitAnd => a & b,
+        BitOr => a | b,
+        BitXor => a ^ b,
+        Shl if a.is_zero() => a,
+        Shl => match b.to_u32() {
+            Some(b) => a << b,
+            None => return Some(Err(too_large())),
+        },
+        _ => match b.to_u64() {
+            Some(b) => a >> b,
+            None if a.is_negative() => BigInt::from(-1),
+            None => BigInt::zero(),
+        },
+    };
+    Some(Ok(normalize_int(result)))
+}
+
+/// Negates a number. Returns `None` if the value is not a number.
+pub fn negate(a: &Value) -> Option<Value> {
+    let result = match a {
+        Value::Int(a) => match a.checked_neg() {
+            Some(a) => Value::Int(a),
+            None => normalize_int(-BigInt::from(*a)),
+        },
+        Value::BigInt(a) => normalize_int(-a),
+        Value::Ratio(a) => Value::Ratio(-a),
+        Value::Float(a) => Value::Float(-a),
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// The number of bits needed to store an int, not counting its sign.
+/// Returns `None` if the value is not an int.
+pub fn bits(a: &Value) -> Option<u64> {
+    match a {
+        Value::Int(a) => Some(64 - a.unsigned_abs().leading_zeros() as u64),
+        Value::BigInt(a) => Some(a.bits()),
+        _ => None,
+    }
+}
+
+/// Converts a float to an exact number, if it is finite
+fn exact_float(a: f64) -> Option<Value> {
+    BigRational::from_float(a).map(normalize_ratio)
+}
+
+/// Compares two numbers by their exact value. Returns `None` if either
+/// number is not a number, or is `NaN`.
+pub fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+
+        // Floats are converted to exact numbers for the comparison,
+        // unless they are infinite
+        (Value::Float(a), b) => match exact_float(*a) {
+            Some(a) => compare(&a, b),
+            None if a.is_nan() || !b.is_number() => None,
+            None => Some(a.total_cmp(&0.0)),
+        },
+        (a, Value::Float(_)) => compare(b, a).map(Ordering::reverse),
+
+        (a, b) => match (Level::of(a)?.raise(1), Level::of(b)?.raise(1)) {
+            (Level::Ratio(a), Level::Ratio(b)) => Some(a.cmp(&b)),
+            _ => unreachable!("exact numbers are raised to ratios"),
+        },
+    }
+}
+
+/// Hashes a number, so that equal numbers hash the same regardless of
+/// their representation. Big ints and ratios are normalized first, since
+/// values created from Rust may not be.
+pub fn hash<H: Hasher>(a: &Value, state: &mut H) {
+    match a {
+        Value::Int(a) => a.hash(state),
+        Value::BigInt(a) => match a.to_i64() {
+            Some(a) => a.hash(state),
+            None => a.hash(state),
+        },
+        Value::Ratio(a) => match normalize_ratio(a.reduced()) {
+            Value::Ratio(a) => a.hash(state),
+            int => hash(&int, state),
+        },
+        Value::Float(a) if a.is_nan() => f64::NAN.to_bits().hash(state),
+        Value::Float(a) => match exact_float(*a) {
+            Some(exact) => hash(&exact, state),
+            None => a.to_bits().hash(state),
+        },
+        _ => (),
+    }
+}
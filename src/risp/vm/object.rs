@@ -0,0 +1,142 @@
+//! Native objects, which let host programs pass their own Rust values
+//! into risp code.
+//!
+//! A native object is stored in a [`Value::Native`]. Scripts can not
+//! look inside it, but they can pass it around, and call its methods
+//! with `(.method obj args...)`, or `(method obj args...)` if `method`
+//! is not bound to anything else.
+//!
+//! The shorter form is only a method call if `method` is not bound, and
+//! `obj` is a name bound to an object. Bound names take precedence, so
+//! `(get obj "a")` calls the builtin `get`, and `(incr (make-counter))`
+//! is a `NameError`, since the object is not in a name. Use the `.method`
+//! form in those cases. The first argument is only looked up, and not
+//! evaluated, so that a mistyped function name does not run the side
+//! effects of its arguments.
+//!
+//! For example, a counter with an `incr` method:
+//!
+//! ```
+//! use std::cell::Cell;
+//! use risp::{Interpreter, NativeObject, RuntimeError, Value};
+//!
+//! struct Counter(Cell<i64>);
+//!
+//! impl NativeObject for Counter {
+//!     fn type_name(&self) -> &str {
+//!         "counter"
+//!     }
+//!
+//!     fn call_method(
+//!         &self,
+//!         _: &mut Interpreter,
+//!         name: &str,
+//!         _: Vec<Value>,
+//!     ) -> Option<Result<Value, RuntimeError>> {
+//!         match name {
+//!             "incr" => {
+//!                 self.0.set(self.0.get() + 1);
+//!                 Some(Ok(Value::Int(self.0.get())))
+//!             }
+//!             _ => None,
+//!         }
+//!     }
+//! }
+//!
+//! let mut interpreter = Interpreter::new();
+//! interpreter.define_name("counter", Value::native(Counter(Cell::new(0))));
+//!
+//! let value = interpreter.eval_str("(.incr counter) (incr counter)").unwrap();
+//! assert!(matches!(value, Value::Int(2)));
+//! ```
+
+use std::any::Any;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use super::{ErrorKind, Interpreter, RuntimeError, Value};
+
+/// A Rust value that can be stored in a [`Value::Native`]. Only
+/// [`NativeObject::type_name`] has to be implemented.
+pub trait NativeObject: Any {
+    /// The name of the object's type, which is used by `type_name`
+    /// and in error messages.
+    fn type_name(&self) -> &str;
+
+    /// Returns how the object is shown when it is printed. This is
+    /// `<TypeName>` by default.
+    fn repr(&self) -> String {
+        format!("<{}>", self.type_name())
+    }
+
+    /// Calls one of the object's methods with the arguments that
+    /// follow the object. Returns `None` if the object has no method
+    /// with the name. Objects have no methods by default.
+    fn call_method(
+        &self,
+        inter: &mut Interpreter,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Option<Result<Value, RuntimeError>> {
+        let _ = (inter, name, args);
+        None
+    }
+
+    /// Compares the object to another native object with the same type
+    /// name, for equality and sorting. Objects are equal if this returns
+    /// `Ordering::Equal`. Returns `None` if the objects can not be
+    /// compared, in which case they are only equal to themselves, and
+    /// are ordered by their address in memory. No objects can be
+    /// compared by default.
+    /// 
+    /// For values to stay totally ordered, this must either return
+    /// `None` for every pair of objects with the type name, or be a
+    /// total order on them.
+    fn compare(&self, other: &dyn NativeObject) -> Option<Ordering> {
+        let _ = other;
+        None
+    }
+}
+
+impl Value {
+    /// Wraps a Rust value in a [`Value::Native`]
+    pub fn native(object: impl NativeObject) -> Value {
+        Value::Native(Rc::new(object))
+    }
+
+    /// Returns the Rust value inside a [`Value::Native`], if the value
+    /// is a native object of type `T`.
+    pub fn downcast_native<T: NativeObject>(&self) -> Option<&T> {
+        match self {
+            Value::Native(object) => (&**object as &dyn Any).downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl Interpreter {
+    /// Calls a method of the native object which is the first argument,
+    /// with the rest of the arguments, like `(.method obj args...)`.
+    pub(crate) fn call_method(&mut self, method: &str, mut args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let object = match args.first() {
+            Some(Value::Native(object)) => object.clone(),
+            Some(other) => {
+                let msg = format!("{} has no method {method}", other.type_name());
+                return Err(RuntimeError::new(ErrorKind::AttributeError, msg));
+            }
+            None => {
+                let msg = format!(".{method} expected an object to call the method on");
+                return Err(RuntimeError::new(ErrorKind::TypeError, msg));
+            }
+        };
+
+        let rest = args.split_off(1);
+        match object.call_method(self, method, rest) {
+            Some(result) => result,
+            None => {
+                let msg = format!("{} has no method {method}", object.type_name());
+                Err(RuntimeError::new(ErrorKind::AttributeError, msg))
+            }
+        }
+    }
+}
@@ -1,34 +1,159 @@
-use crate::{AstNode, risp::{Op, ErrorKind}};
-use super::{Interpreter, RuntimeError};
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use indexmap::{IndexMap, IndexSet};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::Signed;
+
+use crate::{AstNode, FileId, risp::{Op, ErrorKind}};
+use super::bytecode::Chunk;
+use super::number;
+use super::{Env, MacroFn, NativeFn, NativeObject, RuntimeError};
 
 #[derive(Clone)]
 pub enum Value {
-    Int(i32),
+    Int(i64),
+    /// An int that does not fit in 64 bits. Ints are only stored as big
+    /// ints when they are too large for [`Value::Int`], although big
+    /// ints created from Rust that would fit still compare and hash
+    /// like the equal int.
+    BigInt(BigInt),
+    /// An exact fraction, which is never whole when it is produced by
+    /// risp code. Like big ints, ratios created from Rust are compared
+    /// and hashed by their value.
+    Ratio(BigRational),
     Bool(bool),
     Float(f64),
     Str(String),
     List(Vec<Value>),
-    RustFn(fn (&mut Interpreter, Vec<Value>) -> Result<Vec<Value>, RuntimeError>),
-    RustMacro(fn (&mut Interpreter, &[AstNode]) -> Result<Value, RuntimeError>),
+    /// A map from keys to values, which remembers the order that keys
+    /// were inserted in.
+    Map(IndexMap<Value, Value>),
+    /// A set of values, which remembers the order that values were
+    /// inserted in.
+    Set(IndexSet<Value>),
+    /// A sequence of ints, which are only produced when it is
+    /// iterated over.
+    Range(Range),
+    RustFn(Rc<NativeFn>),
+    RustMacro(MacroFn),
+    Lambda(Rc<Lambda>),
+    /// A macro defined in risp code, using `defmacro`. It is called
+    /// with its arguments as data, and returns the code to evaluate in
+    /// place of the call.
+    Macro(Rc<Lambda>),
     Operator(Op),
     Symbol(String),
+    /// An object from the host program. Its methods can be called with
+    /// `(.method obj args...)`.
+    Native(Rc<dyn NativeObject>),
     Null,
 }
 
+/// The ints from `start` up to, but not including, `end`, counting in
+/// steps of `step`. Ranges with a negative step count down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    /// The difference between consecutive ints, which is never zero
+    pub step: i64,
+}
+
+impl Range {
+    /// Iterates over the ints in the range
+    pub fn iter(self) -> impl Iterator<Item = i64> {
+        let Range { start, end, step } = self;
+        std::iter::successors(Some(start), move |i| i.checked_add(step))
+            .take_while(move |i| if step > 0 { *i < end } else { *i > end })
+    }
+}
+
+/// A function defined in risp code, using the `fn` or `defn` macros.
+pub struct Lambda {
+    /// The name the function was defined with. Anonymous functions
+    /// do not have a name.
+    pub name: Option<String>,
+    /// The names that arguments are bound to when the function is called.
+    pub params: Vec<String>,
+    /// The name that any arguments after the parameters are bound to,
+    /// as a list. This is written after a `&`, like `(fn (a & rest))`.
+    pub rest: Option<String>,
+    /// The expressions that are evaluated when the function is called.
+    pub body: Vec<AstNode>,
+    /// The scope that the function was defined in.
+    pub env: Env,
+    /// The compiled body of the function. This is compiled the first
+    /// time the function is called from bytecode.
+    pub chunk: OnceCell<Rc<Chunk>>,
+    /// Keeps the source of the function's body from being reused while
+    /// the function exists. The handles are never read.
+    #[allow(dead_code)]
+    pub(crate) files: Vec<Rc<FileId>>,
+}
+
+impl Lambda {
+    /// Checks that the function was called with the right number of
+    /// arguments.
+    pub fn check_arity(&self, argc: usize) -> Result<(), RuntimeError> {
+        let min = self.params.len();
+        let max = match self.rest {
+            Some(_) => usize::MAX,
+            None => min,
+        };
+        if (min..=max).contains(&argc) {
+            return Ok(());
+        }
+
+        let name = self.name.as_deref().unwrap_or("<lambda>");
+        Err(RuntimeError::arity(name, min, max, argc))
+    }
+
+    /// Pairs the parameters with the arguments of a call, which must
+    /// already have been checked with [`Lambda::check_arity`]. Extra
+    /// arguments are collected into a list for the rest parameter.
+    pub fn bind_args(&self, mut args: Vec<Value>) -> Vec<(&str, Value)> {
+        let extra = args.split_off(self.params.len());
+        let mut bindings: Vec<(&str, Value)> = self.params.iter().map(String::as_str).zip(args).collect();
+
+        if let Some(rest) = &self.rest {
+            bindings.push((rest, List(extra)));
+        }
+        bindings
+    }
+}
+
 use Value::*;
 
 impl Value {
+    /// Returns `true` if the value is a function or an operator, which
+    /// can be called with evaluated arguments.
+    pub fn is_callable(&self) -> bool {
+        matches!(self, RustFn(_) | Lambda(_) | Operator(_))
+    }
+
     pub fn type_name(&self) -> String {
         match self {
-            Int(_) => "int".into(),
+            Int(_) | BigInt(_) => "int".into(),
+            Ratio(_) => "ratio".into(),
             Bool(_) => "bool".into(),
             Float(_) => "float".into(),
             Str(_) => "str".into(),
             List(_) => "list".into(),
+            Map(_) => "map".into(),
+            Set(_) => "set".into(),
+            Range(_) => "range".into(),
             RustFn(_) => "rustfn".into(),
             RustMacro(_) => "rustmacro".into(),
+            Lambda(_) => "function".into(),
+            Macro(_) => "macro".into(),
             Operator(_) => "operator".into(),
             Symbol(_) => "symbol".into(),
+            Native(object) => object.type_name().into(),
             Null => "null".into()
         }
     }
@@ -36,75 +161,312 @@ impl Value {
     pub fn repr(&self) -> String {
         match self {
             Int(a) => a.to_string(),
+            BigInt(a) => a.to_string(),
+            Ratio(a) => a.to_string(),
             Bool(a) => a.to_string(),
             Float(a) => a.to_string(),
-            Str(a) => a.to_string(),
-            List(_) => "[]".into(),
+            Str(a) => format!("{a:?}"),
+            List(l) => {
+                let elems: Vec<String> = l.iter().map(|v| v.repr()).collect();
+                format!("[{}]", elems.join(" "))
+            }
+            Map(m) => {
+                let entries: Vec<String> = m
+                    .iter()
+                    .map(|(k, v)| format!("{} {}", k.repr(), v.repr()))
+                    .collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+            Set(s) => {
+                let elems: Vec<String> = s.iter().map(|v| v.repr()).collect();
+                format!("#{{{}}}", elems.join(" "))
+            }
+            Range(r) if r.step == 1 => format!("(range {} {})", r.start, r.end),
+            Range(r) => format!("(range {} {} {})", r.start, r.end, r.step),
             RustFn(_) => "<Rust Function>".into(),
             RustMacro(_) => "<Rust Macro>".into(),
-            Operator(a) => match a {
-                Op::Plus => "+".into(),
-                Op::Minus => "-".into(),
-                Op::Star => "*".into(),
-                Op::Slash => "/".into(),
-                Op::Equal => "=".into(),
-                Op::Greater => ">".into(),
-                Op::Less => "<".into()
+            Native(object) => object.repr(),
+            Lambda(f) => match &f.name {
+                Some(name) => format!("<Function {name}>"),
+                None => "<Function>".into(),
+            },
+            Macro(m) => match &m.name {
+                Some(name) => format!("<Macro {name}>"),
+                None => "<Macro>".into(),
             },
+            Operator(a) => a.as_str().into(),
             Symbol(s) => format!("<Symbol {s}>"),
             Null => "null".into()
         }
     }
 
+    /// Converts the value to a string for printing. This is the same
+    /// as [`Value::repr`], except that strings are not quoted.
     pub fn display(&self) -> String {
-        self.repr()
-    }
-
-    pub fn oneside_binary_op(&self, rhs: &Value, op: &Op) -> Result<Value, RuntimeError> {
-        macro_rules! impl_default {
-            ($a:expr, $b:expr, $out:ident) => {{
-                use Op::*;
-                let value = match op {
-                    Plus => $out($a + $b),
-                    Minus => $out($a - $b),
-                    Star => $out($a * $b),
-                    Slash => $out($a / $b),
-                    Equal => Bool($a == $b),
-                    Greater => Bool($a > $b),
-                    Less => Bool($a < $b),
-                };
-                return Ok(value)
-            }}
-        }
-        
-        match (self, rhs) {
-            (Int(a), Int(b)) => impl_default!(a, b, Int),
-            (Bool(a), Int(b)) => impl_default!(&(*a as i32), b, Int),
-            (Float(a), Int(b)) => impl_default!(a, &(*b as f64), Float),
-            (Str(a), Str(b)) => {
-                if op == &Op::Plus {
-                    return Ok(Str(a.clone() + b))
-                }
+        match self {
+            Str(a) => a.clone(),
+            _ => self.repr(),
+        }
+    }
+
+    /// Returns `true` if the value is an int, ratio or float
+    pub fn is_number(&self) -> bool {
+        matches!(self, Int(_) | BigInt(_) | Ratio(_) | Float(_))
+    }
+
+    /// Applies a binary operator to two values. Arithmetic follows the
+    /// rules of the [numeric tower](number). Strings can also be joined
+    /// with `+`, and repeated with `*`.
+    pub fn binary_op(&self, rhs: &Value, op: &Op) -> Result<Value, RuntimeError> {
+        let numeric = number::arith(*op, self, rhs).or_else(|| number::bitwise(*op, self, rhs));
+        if let Some(result) = numeric {
+            return result;
+        }
+
+        match (self, rhs, op) {
+            (Str(a), Str(b), Op::Plus) => Ok(Str(a.clone() + b)),
+            (Str(a), times, Op::Star) | (times, Str(a), Op::Star) if times.is_integer() => {
+                repeat(a, times)
+            }
+            _ => {
+                let msg = format!(
+                    "unsupported operand types for {}: {} and {}",
+                    op.as_str(),
+                    self.type_name(),
+                    rhs.type_name()
+                );
+                Err(RuntimeError::new(ErrorKind::TypeError, msg))
             }
-            (Str(a), Int(b)) => {
-                if op == &Op::Star {
-                    return Ok(Str(a.repeat(*b as usize)))
-                }
+        }
+    }
+
+    /// Returns `true` if the value is an int or a big int
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Int(_) | BigInt(_))
+    }
+
+    /// Iterates over the elements of a list, set or range, the
+    /// characters of a string, or the entries of a map, which are given
+    /// as `[key value]` lists. Returns `None` for other values.
+    pub fn iter(&self) -> Option<Box<dyn Iterator<Item = Value> + '_>> {
+        match self {
+            List(l) => Some(Box::new(l.iter().cloned())),
+            Set(s) => Some(Box::new(s.iter().cloned())),
+            Range(r) => Some(Box::new(r.iter().map(Int))),
+            Str(s) => Some(Box::new(s.chars().map(|c| Str(c.into())))),
+            Map(m) => Some(Box::new(m.iter().map(|(k, v)| List(vec![k.clone(), v.clone()])))),
+            _ => None,
+        }
+    }
+}
+
+/// Repeats a string a number of times. Repeating a string a negative
+/// number of times gives an empty string. Returns a `MemoryError`
+/// instead of allocating if the result could not be allocated.
+fn repeat(s: &str, times: &Value) -> Result<Value, RuntimeError> {
+    let times = match times {
+        Int(n) => usize::try_from(*n).unwrap_or(0),
+        BigInt(n) if n.is_negative() => 0,
+        _ => usize::MAX,
+    };
+
+    let too_large = || RuntimeError::new(ErrorKind::MemoryError, "repeated string is too large");
+
+    // Allocations can not be larger than `isize::MAX` bytes
+    let len = s
+        .len()
+        .checked_mul(times)
+        .filter(|len| isize::try_from(*len).is_ok())
+        .ok_or_else(too_large)?;
+
+    let mut repeated = String::new();
+    if len > 0 {
+        repeated.try_reserve_exact(len).map_err(|_| too_large())?;
+        for _ in 0..times {
+            repeated.push_str(s);
+        }
+    }
+    Ok(Str(repeated))
+}
+
+/// Values are debugged as their representation in risp code.
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.repr())
+    }
+}
+
+/// Values are compared structurally. Numbers are compared by their
+/// exact value, so `1` and `1.0` are equal. For equality to be total,
+/// `NaN` is considered equal to itself. Functions are only equal
+/// to themselves, and native objects are only equal to themselves
+/// unless they implement [`NativeObject::compare`].
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Int(a), Int(b)) => a == b,
+            (Float(a), Float(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (a, b) if a.is_number() && b.is_number() => {
+                number::compare(a, b) == Some(Ordering::Equal)
+            }
+            (Bool(a), Bool(b)) => a == b,
+            (Str(a), Str(b)) => a == b,
+            (Symbol(a), Symbol(b)) => a == b,
+            (List(a), List(b)) => a == b,
+            (Map(a), Map(b)) => a == b,
+            (Set(a), Set(b)) => a == b,
+            (Range(a), Range(b)) => a == b,
+            (RustFn(a), RustFn(b)) => Rc::ptr_eq(a, b),
+            (RustMacro(a), RustMacro(b)) => std::ptr::fn_addr_eq(*a, *b),
+            (Lambda(a), Lambda(b)) | (Macro(a), Macro(b)) => Rc::ptr_eq(a, b),
+            (Operator(a), Operator(b)) => a == b,
+            (Native(a), Native(b)) => {
+                Rc::ptr_eq(a, b)
+                    || (a.type_name() == b.type_name() && a.compare(&**b) == Some(Ordering::Equal))
+            }
+            (Null, Null) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+/// Hashes are consistent with equality: equal numbers hash the same,
+/// whatever their representation, and maps and sets hash the same
+/// regardless of the order of their entries.
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Hashes a single value on its own, so that the hashes of
+        // unordered entries can be combined.
+        fn hash_one(value: &impl Hash) -> u64 {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        // Numbers are hashed by their numeric value alone, so that equal
+        // numbers hash the same.
+        if self.is_number() {
+            return number::hash(self, state);
+        }
+        std::mem::discriminant(self).hash(state);
+
+        match self {
+            Int(_) | BigInt(_) | Ratio(_) | Float(_) => unreachable!(),
+            Bool(a) => a.hash(state),
+            Str(a) => a.hash(state),
+            Symbol(a) => a.hash(state),
+            List(a) => a.hash(state),
+            Map(a) => {
+                let sum = a.iter().fold(0u64, |sum, entry| sum.wrapping_add(hash_one(&entry)));
+                (a.len(), sum).hash(state)
+            }
+            Set(a) => {
+                let sum = a.iter().fold(0u64, |sum, value| sum.wrapping_add(hash_one(value)));
+                (a.len(), sum).hash(state)
+            }
+            Range(a) => a.hash(state),
+            RustFn(a) => Rc::as_ptr(a).cast::<()>().hash(state),
+            RustMacro(a) => (*a as usize).hash(state),
+            Lambda(a) | Macro(a) => Rc::as_ptr(a).hash(state),
+            Operator(a) => a.hash(state),
+            // Objects may be equal to other objects of the same type
+            Native(a) => a.type_name().hash(state),
+            Null => (),
+        }
+    }
+}
+
+/// Values have a total ordering, so that any values can be sorted.
+///
+/// Values of different types are ordered by their type, in this order:
+/// null, bools, numbers (ints, big ints, ratios and floats together),
+/// strings, symbols, lists, sets, maps, ranges, operators, built-in
+/// functions, built-in macros, lambdas, macros, and native objects.
+///
+/// Within each type:
+/// - `false` comes before `true`
+/// - numbers are compared by their exact value, with `NaN` after every
+///   other number
+/// - strings and symbols are ordered lexicographically
+/// - lists are ordered lexicographically by their elements
+/// - sets and maps are ordered by their sorted elements, since the
+///   order of insertion does not affect equality
+/// - ranges are ordered by their start, then their end, then their step
+/// - functions and macros are ordered by their address in memory, which
+///   is arbitrary but consistent
+/// - native objects are ordered by their type name, then by
+///   [`NativeObject::compare`], or by their address if they can not be
+///   compared
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        /// Returns `true` if a number is `NaN`
+        fn is_nan(a: &Value) -> bool {
+            matches!(a, Float(a) if a.is_nan())
+        }
+
+        /// Sorts the elements of an unordered collection
+        fn sorted<T: Ord>(items: impl Iterator<Item = T>) -> Vec<T> {
+            let mut items: Vec<T> = items.collect();
+            items.sort();
+            items
+        }
+
+        match (self, other) {
+            (Int(a), Int(b)) => a.cmp(b),
+            (a, b) if a.is_number() && b.is_number() => match number::compare(a, b) {
+                Some(ordering) => ordering,
+                None => is_nan(a).cmp(&is_nan(b)),
             },
-            _ => (),
+            (Bool(a), Bool(b)) => a.cmp(b),
+            (Str(a), Str(b)) => a.cmp(b),
+            (Symbol(a), Symbol(b)) => a.cmp(b),
+            (List(a), List(b)) => a.cmp(b),
+            (Set(a), Set(b)) => sorted(a.iter()).cmp(&sorted(b.iter())),
+            (Map(a), Map(b)) => sorted(a.iter()).cmp(&sorted(b.iter())),
+            (Range(a), Range(b)) => a.cmp(b),
+            (Operator(a), Operator(b)) => a.cmp(b),
+            (RustFn(a), RustFn(b)) => Rc::as_ptr(a).cast::<()>().cmp(&Rc::as_ptr(b).cast::<()>()),
+            (RustMacro(a), RustMacro(b)) => (*a as usize).cmp(&(*b as usize)),
+            (Lambda(a), Lambda(b)) | (Macro(a), Macro(b)) => Rc::as_ptr(a).cmp(&Rc::as_ptr(b)),
+            (Native(a), Native(b)) if Rc::ptr_eq(a, b) => Ordering::Equal,
+            (Native(a), Native(b)) => a.type_name().cmp(b.type_name()).then_with(|| {
+                a.compare(&**b)
+                    .unwrap_or_else(|| Rc::as_ptr(a).cast::<()>().cmp(&Rc::as_ptr(b).cast::<()>()))
+            }),
+            _ => self.type_rank().cmp(&other.type_rank()),
         }
+    }
+}
 
-        return Err(RuntimeError {
-            kind: ErrorKind::TypeError,
-            msg: "".into()
-        })
-        
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
+}
 
-    pub fn binary_op(&self, rhs: &Value, op: &Op) -> Result<Value, RuntimeError> {
-        return match self.oneside_binary_op(rhs, op) {
-            Err(_) => rhs.oneside_binary_op(self, op),
-            ok => ok
+impl Value {
+    /// The position of the value's type in the ordering of values.
+    /// All numbers have the same rank, since they are compared by
+    /// their numeric value.
+    fn type_rank(&self) -> u8 {
+        match self {
+            Null => 0,
+            Bool(_) => 1,
+            Int(_) | BigInt(_) | Ratio(_) | Float(_) => 2,
+            Str(_) => 3,
+            Symbol(_) => 4,
+            List(_) => 5,
+            Set(_) => 6,
+            Map(_) => 7,
+            Range(_) => 8,
+            Operator(_) => 9,
+            RustFn(_) => 10,
+            RustMacro(_) => 11,
+            Lambda(_) => 12,
+            Macro(_) => 13,
+            Native(_) => 14,
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,580 @@
+//! Checks the library API that Rust programs use to embed risp.
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::error::Error as _;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use risp::{Diagnostic, Error, ErrorKind, Interpreter, Limits, NativeObject, RuntimeError, Value};
+
+#[test]
+fn eval_str_returns_last_value() {
+    let mut interpreter = Interpreter::new();
+
+    let value = interpreter.eval_str("(define x 20) (+ x 22)").unwrap();
+    assert!(matches!(value, Value::Int(42)));
+
+    // Names defined by earlier calls are still visible
+    let value = interpreter.eval_str("(* x 2)").unwrap();
+    assert!(matches!(value, Value::Int(40)));
+
+    let value = interpreter.eval_str("").unwrap();
+    assert!(matches!(value, Value::Null));
+}
+
+#[test]
+fn errors_implement_error_and_display() {
+    let mut interpreter = Interpreter::new();
+
+    let err = interpreter.eval_str("(+ 1").unwrap_err();
+    assert!(matches!(err, Error::Syntax(_)));
+    assert_eq!(err.to_string(), "SyntaxError: unclosed parenthesis");
+    assert!(err.source().is_some());
+
+    let err = interpreter.eval_str("(/ 1 0)").unwrap_err();
+    match &err {
+        Error::Runtime(inner) => assert!(matches!(inner.kind, ErrorKind::ZeroDivisionError)),
+        Error::Syntax(_) => panic!("expected a runtime error"),
+    }
+
+    // Errors can be rendered with the code that caused them
+    let rendered = Diagnostic::from(&err).render(interpreter.sources(), false);
+    assert!(rendered.contains("(/ 1 0)"), "{rendered}");
+
+    // The underline stops at the end of the line, after multibyte text
+    let err = interpreter.eval_str("(define s \"✓✓✓✓✓✓\") (+ s\n 1)").unwrap_err();
+    let rendered = Diagnostic::from(&err).render(interpreter.sources(), false);
+    let underline = format!("  | {}^^^^", " ".repeat(20));
+    assert_eq!(rendered.lines().last(), Some(underline.as_str()), "{rendered}");
+}
+
+#[test]
+fn arity_errors_share_their_wording() {
+    let mut interpreter = Interpreter::new();
+
+    let errors = [
+        ("(not true false)", "not expected 1 argument, found 2"),
+        ("(throw)", "throw expected 1 argument, found 0"),
+        ("(quote 1 2)", "quote expected 1 argument, found 2"),
+        ("(eval)", "eval expected 1 argument, found 0"),
+        ("(define x)", "define expected 2 arguments, found 1"),
+        ("(if true)", "if expected 2 to 3 arguments, found 1"),
+        ("(while true (break 1 2))", "break expected 0 to 1 arguments, found 2"),
+        ("(while true (continue 1))", "continue expected 0 arguments, found 1"),
+        ("(apply +)", "apply expected at least 2 arguments, found 1"),
+        ("(+)", "+ expected at least 1 argument, found 0"),
+        ("((fn (a & rest) a))", "<lambda> expected at least 1 argument, found 0"),
+    ];
+    for (src, expected) in errors {
+        let err = interpreter.eval_str(src).unwrap_err();
+        assert_eq!(err.to_string(), format!("TypeError: {expected}"), "{src}");
+    }
+}
+
+#[test]
+fn builder_chooses_stdlib_modules() {
+    let mut interpreter = Interpreter::builder().collections(false).build();
+    assert!(interpreter.eval_str("(range 3)").is_err());
+    assert!(interpreter.eval_str("(if true 1 2)").is_ok());
+
+    let mut interpreter = Interpreter::builder().macros(false).build();
+    assert!(interpreter.eval_str("(define x 1)").is_err());
+    assert!(matches!(interpreter.eval_str("(+ 1 2)"), Ok(Value::Int(3))));
+}
+
+#[test]
+fn builder_sets_limits() {
+    let limits = Limits { max_steps: Some(1000), ..Limits::default() };
+    let mut interpreter = Interpreter::builder().limits(limits).build();
+
+    match interpreter.eval_str("(while true null)") {
+        Err(Error::Runtime(err)) => assert!(matches!(err.kind, ErrorKind::LimitExceeded)),
+        _ => panic!("expected the step limit to be exceeded"),
+    }
+}
+
+#[test]
+fn deep_recursion_does_not_overflow_small_stacks() {
+    let thread = std::thread::Builder::new().stack_size(256 * 1024).spawn(|| {
+        let mut interpreter = Interpreter::new();
+
+        let src = "(defn f (n) (for (x (list 1)) (f n))) (f 1)";
+        let err = interpreter.eval_str(src).unwrap_err();
+        assert_eq!(err.to_string(), "RecursionError: maximum call depth of 1000 exceeded");
+        let err = tree_walk(&mut interpreter, "(defn g (n) (+ 1 (g n))) (g 1)").unwrap_err();
+        assert_eq!(err.to_string(), "RecursionError: maximum call depth of 1000 exceeded");
+
+        // Nesting below the limits is evaluated normally
+        let nested = format!("{}1{}", "(- ".repeat(900), ")".repeat(900));
+        assert!(matches!(interpreter.eval_str(&nested), Ok(Value::Int(1))));
+        assert!(matches!(tree_walk(&mut interpreter, &nested), Ok(Value::Int(1))));
+
+        let nested = format!("{}1{}", "(- ".repeat(5000), ")".repeat(5000));
+        let err = interpreter.eval_str(&nested).unwrap_err();
+        assert_eq!(err.to_string(), "SyntaxError: expressions can not be nested more than 1000 deep");
+        let nested = format!("{}x", "'".repeat(5000));
+        assert!(matches!(interpreter.eval_str(&nested), Err(Error::Syntax(_))));
+    });
+
+    thread.unwrap().join().unwrap();
+}
+
+#[test]
+fn unlimited_sizes_do_not_crash() {
+    let limits = Limits { max_size: None, ..Limits::default() };
+    let mut interpreter = Interpreter::builder().limits(limits).build();
+
+    let err = interpreter.eval_str(r#"(* "ab" 9000000000000000000)"#).unwrap_err();
+    assert_eq!(err.to_string(), "MemoryError: repeated string is too large");
+    let value = interpreter.eval_str(r#"(list (* "ab" 3) (* "" 9000000000000000000))"#).unwrap();
+    assert_eq!(value.repr(), r#"["ababab" ""]"#);
+}
+
+#[test]
+fn registered_closures_capture_state() {
+    let mut interpreter = Interpreter::new();
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let log = events.clone();
+    interpreter.register_fn("log", move |event: String| log.borrow_mut().push(event));
+
+    interpreter.eval_str(r#"(log "start") (dotimes (i 2) (log "tick"))"#).unwrap();
+    assert_eq!(*events.borrow(), ["start", "tick", "tick"]);
+}
+
+#[test]
+fn registered_closures_convert_arguments() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_fn("sum", |xs: Vec<i64>| xs.iter().sum::<i64>());
+    interpreter.register_fn("scale", |x: f64, by: Option<f64>| x * by.unwrap_or(2.0));
+    interpreter.register_fn("repeat", |s: String, n: i64| -> Result<Vec<String>, RuntimeError> {
+        match usize::try_from(n) {
+            Ok(n) => Ok(vec![s; n]),
+            Err(_) => Err(RuntimeError::new(ErrorKind::ValueError, "count must not be negative")),
+        }
+    });
+
+    let value = interpreter.eval_str("(sum (list 1 2 3))").unwrap();
+    assert!(matches!(value, Value::Int(6)));
+    let value = interpreter.eval_str("(scale 3 null)").unwrap();
+    assert!(matches!(value, Value::Float(f) if f == 6.0));
+    let value = interpreter.eval_str(r#"(repeat "ab" 2)"#).unwrap();
+    assert_eq!(value.repr(), r#"["ab" "ab"]"#);
+
+    let errors = [
+        ("(sum 1 2)", "TypeError: sum expected 1 argument, found 2"),
+        (r#"(sum (list 1 "2"))"#, "TypeError: sum expected a list of ints, found list"),
+        (r#"(scale "3" 1)"#, "TypeError: scale expected a number, found str"),
+        (r#"(repeat "ab" -1)"#, "ValueError: count must not be negative"),
+    ];
+    for (src, expected) in errors {
+        let err = interpreter.eval_str(src).unwrap_err();
+        assert_eq!(err.to_string(), expected);
+    }
+}
+
+#[test]
+fn native_functions_receive_the_interpreter() {
+    let mut interpreter = Interpreter::new();
+    let three = Value::RustFn(Rc::new(|inter: &mut Interpreter, _| {
+        match inter.eval_str("(+ 1 2)") {
+            Ok(value) => Ok(vec![value]),
+            Err(_) => Err(RuntimeError::new(ErrorKind::ValueError, "could not add")),
+        }
+    }));
+    interpreter.define_name("three", three);
+
+    let value = interpreter.eval_str("(three)").unwrap();
+    assert!(matches!(value, Value::Int(3)));
+}
+
+#[test]
+fn host_calls_functions_defined_in_scripts() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str(
+            r#"
+            (define events 0)
+            (defn on_event (name count)
+                (set events (+ events count))
+                (list name events))
+            "#,
+        )
+        .unwrap();
+
+    let handler = interpreter.get_fn("on_event").expect("on_event should be defined");
+    let value = interpreter.call(&handler, ("click", 2)).unwrap();
+    assert_eq!(value.repr(), r#"["click" 2]"#);
+    let value = interpreter.call(&handler, vec![Value::Str("key".into()), Value::Int(3)]).unwrap();
+    assert_eq!(value.repr(), r#"["key" 5]"#);
+
+    // Builtins, operators and anonymous functions can be called too
+    let add = interpreter.eval_str("+").unwrap();
+    assert!(matches!(interpreter.call(&add, [1, 2, 3]), Ok(Value::Int(6))));
+    let double = interpreter.eval_str("(fn (x) (* x 2))").unwrap();
+    assert!(matches!(interpreter.call(&double, (21,)), Ok(Value::Int(42))));
+
+    // Names that are missing or not callable have no function
+    assert!(interpreter.get_fn("on_missing").is_none());
+    assert!(interpreter.get_fn("events").is_none());
+    assert!(interpreter.get_fn("println").is_some());
+}
+
+#[test]
+fn host_calls_report_errors() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str("(defn fail (x) (/ x 0))").unwrap();
+    let fail = interpreter.get_fn("fail").unwrap();
+
+    match interpreter.call(&fail, (1,)) {
+        Err(Error::Runtime(err)) => assert!(matches!(err.kind, ErrorKind::ZeroDivisionError)),
+        _ => panic!("expected a ZeroDivisionError"),
+    }
+    let err = interpreter.call(&fail, ()).unwrap_err();
+    assert_eq!(err.to_string(), "TypeError: fail expected 1 argument, found 0");
+    let err = interpreter.call(&Value::Int(1), ()).unwrap_err();
+    assert_eq!(err.to_string(), "TypeError: int is not callable");
+
+    // The interpreter can still be used after an error
+    assert!(matches!(interpreter.eval_str("(+ 1 1)"), Ok(Value::Int(2))));
+}
+
+/// A native object with a method table
+struct Account {
+    owner: String,
+    balance: Cell<i64>,
+}
+
+impl NativeObject for Account {
+    fn type_name(&self) -> &str {
+        "account"
+    }
+
+    fn repr(&self) -> String {
+        format!("<account {}: {}>", self.owner, self.balance.get())
+    }
+
+    fn call_method(
+        &self,
+        _: &mut Interpreter,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Option<Result<Value, RuntimeError>> {
+        let result = match (name, &args[..]) {
+            ("balance", []) => Ok(Value::Int(self.balance.get())),
+            ("deposit", [Value::Int(amount)]) => {
+                self.balance.set(self.balance.get() + amount);
+                Ok(Value::Int(self.balance.get()))
+            }
+            ("deposit", _) => Err(RuntimeError::new(ErrorKind::TypeError, "deposit expected an int")),
+            _ => return None,
+        };
+        Some(result)
+    }
+}
+
+fn account(owner: &str) -> Value {
+    Value::native(Account { owner: owner.into(), balance: Cell::new(0) })
+}
+
+/// Evaluates code with the tree-walking interpreter, returning the value
+/// of the last expression
+fn tree_walk(interpreter: &mut Interpreter, src: &str) -> Result<Value, RuntimeError> {
+    let nodes = interpreter.load("<test>", src).unwrap();
+    let mut value = Value::Null;
+    for node in &nodes {
+        value = interpreter.eval(node).map_err(|control| control.into_error())?;
+    }
+    Ok(value)
+}
+
+#[test]
+fn native_objects_have_methods() {
+    let mut interpreter = Interpreter::new();
+    interpreter.define_name("acct", account("ada"));
+
+    let value = interpreter.eval_str("(.deposit acct 10) (deposit acct 5) (.balance acct)").unwrap();
+    assert!(matches!(value, Value::Int(15)));
+    let value = tree_walk(&mut interpreter, "(.deposit acct 10) (deposit acct 5) (balance acct)").unwrap();
+    assert!(matches!(value, Value::Int(30)));
+
+    // Methods can be called from compiled function bodies and with locals
+    let value = interpreter.eval_str("(defn add (a n) (deposit a n)) (let ((a acct)) (add a 2))").unwrap();
+    assert!(matches!(value, Value::Int(32)));
+
+    // Names that are bound are not treated as methods
+    interpreter.eval_str("(defn balance (x) \"function\")").unwrap();
+    let value = interpreter.eval_str("(balance acct)").unwrap();
+    assert_eq!(value.repr(), "\"function\"");
+
+    let err = interpreter.eval_str("(.withdraw acct 1)").unwrap_err();
+    assert_eq!(err.to_string(), "AttributeError: account has no method withdraw");
+    let err = interpreter.eval_str("(withdraw acct 1)").unwrap_err();
+    assert_eq!(err.to_string(), "AttributeError: account has no method withdraw");
+    let err = tree_walk(&mut interpreter, "(.withdraw acct 1)").unwrap_err();
+    assert_eq!(err.to_string(), "AttributeError: account has no method withdraw");
+    let err = interpreter.eval_str("(.deposit acct \"all\")").unwrap_err();
+    assert_eq!(err.to_string(), "TypeError: deposit expected an int");
+
+    // Bound names are never methods, and only names are looked at for an
+    // object, so these need the `.method` form
+    let err = interpreter.eval_str("(keys acct)").unwrap_err();
+    assert_eq!(err.to_string(), "TypeError: keys expected a map, found account");
+    let open = Value::RustFn(Rc::new(|_: &mut Interpreter, _| Ok(vec![account("eve")])));
+    interpreter.define_name("open", open);
+    let err = interpreter.eval_str("(deposit (open) 1)").unwrap_err();
+    assert_eq!(err.to_string(), "NameError: deposit is not defined");
+    let err = tree_walk(&mut interpreter, "(deposit (open) 1)").unwrap_err();
+    assert_eq!(err.to_string(), "NameError: deposit is not defined");
+    let value = interpreter.eval_str("(.deposit (open) 1)").unwrap();
+    assert!(matches!(value, Value::Int(1)));
+
+    // Unbound names that are not called on objects are still errors
+    let err = interpreter.eval_str("(withdraw 1)").unwrap_err();
+    assert_eq!(err.to_string(), "NameError: withdraw is not defined");
+    let err = interpreter.eval_str("(.withdraw 1)").unwrap_err();
+    assert_eq!(err.to_string(), "AttributeError: int has no method withdraw");
+}
+
+#[test]
+fn native_objects_are_values() {
+    let mut interpreter = Interpreter::new();
+    interpreter.define_name("a", account("ada"));
+    interpreter.define_name("b", account("bob"));
+
+    let value = interpreter.eval_str("(deposit a 3) a").unwrap();
+    assert_eq!(value.repr(), "<account ada: 3>");
+    assert_eq!(value.type_name(), "account");
+    let value = interpreter.eval_str("(list a b)").unwrap();
+    assert_eq!(value.repr(), "[<account ada: 3> <account bob: 0>]");
+    let err = interpreter.eval_str("(+ a 1)").unwrap_err();
+    assert_eq!(err.to_string(), "TypeError: unsupported operand types for +: account and int");
+
+    // Objects are only equal to themselves
+    let value = interpreter.eval_str("(list (= a a) (= a b) (contains? #{a} a) (get {a 1} b))").unwrap();
+    assert_eq!(value.repr(), "[true false true null]");
+
+    // The host can get its Rust value back
+    let value = interpreter.eval_str("(get (list a b) 0)").unwrap();
+    let account = value.downcast_native::<Account>().unwrap();
+    assert_eq!(account.owner, "ada");
+    assert!(Value::Int(1).downcast_native::<Account>().is_none());
+}
+
+/// A native object which is compared by its number
+struct Version(u32);
+
+impl NativeObject for Version {
+    fn type_name(&self) -> &str {
+        "version"
+    }
+
+    fn repr(&self) -> String {
+        format!("<version {}>", self.0)
+    }
+
+    fn compare(&self, other: &dyn NativeObject) -> Option<Ordering> {
+        let other = (other as &dyn Any).downcast_ref::<Version>()?;
+        Some(self.0.cmp(&other.0))
+    }
+}
+
+#[test]
+fn native_objects_can_be_compared() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_fn("version", |n: i64| Value::native(Version(n as u32)));
+    interpreter.define_name("acct", account("ada"));
+
+    let value = interpreter
+        .eval_str("(list (= (version 1) (version 1)) (< (version 1) (version 2)) (= (version 1) (version 2)))")
+        .unwrap();
+    assert_eq!(value.repr(), "[true true false]");
+
+    // Equal objects are the same key, and sorting is consistent with equality
+    let value = interpreter.eval_str("(keys {(version 1) 1 (version 1) 2})").unwrap();
+    assert_eq!(value.repr(), "[<version 1>]");
+    let value = interpreter
+        .eval_str("(sort (list (version 3) acct (version 1) (version 2) (version 1)))")
+        .unwrap();
+    assert_eq!(value.repr(), "[<account ada: 0> <version 1> <version 1> <version 2> <version 3>]");
+}
+
+#[test]
+fn arithmetic_normalizes_numbers() {
+    let mut interpreter = Interpreter::new();
+    let mut eval = |src: &str| interpreter.eval_str(src).unwrap();
+
+    // Ints that overflow are promoted to big ints
+    assert!(matches!(eval("(+ 9223372036854775807 1)"), Value::BigInt(n) if n == BigInt::from(i64::MAX) + 1));
+    assert!(matches!(eval("(- -9223372036854775808)"), Value::BigInt(n) if n == -BigInt::from(i64::MIN)));
+    assert!(matches!(eval("(* -9223372036854775808 1)"), Value::Int(i64::MIN)));
+
+    // Big ints and ratios are demoted to ints when they fit in one
+    assert!(matches!(eval("(- (+ 9223372036854775807 1) 1)"), Value::Int(i64::MAX)));
+    assert!(matches!(eval("(- 100000000000000000000 99999999999999999999)"), Value::Int(1)));
+    assert!(matches!(eval("(* (/ 2 3) (/ 3 2))"), Value::Int(1)));
+    assert!(matches!(eval("(/ 10 2)"), Value::Int(5)));
+
+    // Ratios are kept in lowest terms, with the sign on the numerator
+    let parts = match eval("(/ 6 -4)") {
+        Value::Ratio(r) => (r.numer().clone(), r.denom().clone()),
+        other => panic!("expected a ratio, found {other:?}"),
+    };
+    assert_eq!(parts, (BigInt::from(-3), BigInt::from(2)));
+
+    // Floats are contagious
+    assert!(matches!(eval("(+ (/ 1 2) 0.25)"), Value::Float(f) if f == 0.75));
+    assert!(matches!(eval("(* 100000000000000000000 1.5)"), Value::Float(f) if f == 1.5e20));
+}
+
+#[test]
+fn numbers_from_rust_are_compared_by_value() {
+    let mut interpreter = Interpreter::new();
+
+    // Neither of these would be produced by risp code, which would use
+    // ints instead
+    let mut map = IndexMap::new();
+    map.insert(Value::BigInt(BigInt::from(5)), Value::Str("five".into()));
+    map.insert(Value::Ratio(BigRational::new_raw(BigInt::from(4), BigInt::from(2))), Value::Str("two".into()));
+    interpreter.define_name("m", Value::Map(map));
+
+    let value = interpreter.eval_str("(list (get m 5) (get m 2) (get m 2.0) (contains? m 4))").unwrap();
+    assert_eq!(value.repr(), r#"["five" "two" "two" false]"#);
+    let value = interpreter.eval_str("(list (= (keys m) (list 5 2)) (sort (keys m)))").unwrap();
+    assert_eq!(value.repr(), "[true [4/2 5]]");
+}
+
+/// A writer whose contents can still be read after it is given to an
+/// interpreter
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A writer which always fails
+struct BrokenPipe;
+
+impl Write for BrokenPipe {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::BrokenPipe, "the pipe is closed"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn output_and_input_can_be_redirected() {
+    let output = SharedBuffer::default();
+    let mut interpreter = Interpreter::builder()
+        .output(output.clone())
+        .input(&b"ada\nlovelace\n"[..])
+        .build();
+
+    interpreter
+        .eval_str(r#"(println "hello" 1 (list 2 3)) (print "a" "b") (println)"#)
+        .unwrap();
+    assert_eq!(output.contents(), "hello 1 [2 3]\na b\n");
+
+    let value = interpreter.eval_str(r#"(list (input "first:") (input) (input))"#).unwrap();
+    assert_eq!(value.repr(), r#"["ada" "lovelace" ""]"#);
+    assert_eq!(output.contents(), "hello 1 [2 3]\na b\nfirst:");
+
+    // Output can also be redirected after the interpreter is created
+    let other = SharedBuffer::default();
+    interpreter.set_output(other.clone());
+    interpreter.set_input(&b"42\n"[..]);
+    interpreter.eval_str(r#"(println (input "n?"))"#).unwrap();
+    assert_eq!(other.contents(), "n?42\n");
+}
+
+#[test]
+fn io_errors_are_runtime_errors() {
+    let mut interpreter = Interpreter::builder().output(BrokenPipe).build();
+
+    let err = interpreter.eval_str(r#"(println "lost")"#).unwrap_err();
+    assert_eq!(err.to_string(), "IOError: the pipe is closed");
+
+    // Scripts can catch them like any other error
+    let value = interpreter.eval_str(r#"(try (print 1) (catch e "caught"))"#).unwrap();
+    assert_eq!(value.repr(), r#""caught""#);
+}
+
+#[test]
+fn read_string_reuses_its_source() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str(r#"(dotimes (i 100) (read-string "(+ 1 2)") (try (read-string "(") (catch e e)))"#)
+        .unwrap();
+
+    // Only one source is kept for read-string, after eval_str's own
+    assert!(interpreter.sources().get(1).is_some());
+    assert!(interpreter.sources().get(2).is_none());
+}
+
+#[test]
+fn eval_str_reuses_its_source() {
+    let mut interpreter = Interpreter::new();
+    for i in 0..100 {
+        interpreter.eval_str(&format!("(+ {i} 1)")).unwrap();
+    }
+    assert!(interpreter.sources().get(0).is_some());
+    assert!(interpreter.sources().get(1).is_none());
+
+    // Code that defines functions is kept, so that their errors can
+    // still be shown with their source
+    interpreter.eval_str("(defn fail () (/ 1 0))").unwrap();
+    for _ in 0..10 {
+        interpreter.eval_str("(+ 1 2)").unwrap();
+    }
+    let err = interpreter.eval_str("(fail)").unwrap_err();
+    let rendered = Diagnostic::from(&err).render(interpreter.sources(), false);
+    assert!(rendered.contains("(defn fail () (/ 1 0))"), "{rendered}");
+}
+
+#[test]
+fn errors_keep_their_source() {
+    let mut interpreter = Interpreter::new();
+    let render = |err: &Error, interpreter: &Interpreter| Diagnostic::from(err).render(interpreter.sources(), false);
+
+    // The source of an error is kept until the error is dropped, even
+    // if more code is evaluated before it is rendered
+    let err = interpreter.eval_str("(/ 1 0)").unwrap_err();
+    let syntax = interpreter.eval_str("(+ 1").unwrap_err();
+    let read = interpreter.eval_str(r#"(read-string "(- 2")"#).unwrap_err();
+    for _ in 0..10 {
+        interpreter.eval_str(r#"(read-string "(* 3 4)")"#).unwrap();
+    }
+    assert!(render(&err, &interpreter).contains("(/ 1 0)"));
+    assert!(render(&syntax, &interpreter).contains("(+ 1"));
+    assert!(render(&read, &interpreter).contains("(- 2"));
+
+    // Once they are dropped, their files are reused
+    let files = (0..).take_while(|&i| interpreter.sources().get(i).is_some()).count();
+    drop((err, syntax, read));
+    for _ in 0..10 {
+        interpreter.eval_str(r#"(read-string "(* 3 4)")"#).unwrap();
+    }
+    assert!(interpreter.sources().get(files).is_none());
+}
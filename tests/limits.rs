@@ -0,0 +1,125 @@
+//! Checks that code which exceeds the interpreter's limits produces
+//! errors, instead of hanging or crashing.
+
+use std::process::{Command, Stdio};
+use std::{env, fs};
+
+/// Runs a program on both engines with some flags, and returns the
+/// STDERR of each run.
+fn run(name: &str, src: &str, flags: &[&str]) -> Vec<String> {
+    let path = env::temp_dir().join(format!("risp-limits-{}-{name}.risp", std::process::id()));
+    fs::write(&path, src).unwrap();
+
+    let mut outputs = Vec::new();
+    for engine in [None, Some("--tree-walk")] {
+        let output = Command::new(env!("CARGO_BIN_EXE_risp"))
+            .args(engine)
+            .args(flags)
+            .arg(&path)
+            .stdin(Stdio::null())
+            .output()
+            .expect("could not run risp");
+
+        assert!(output.status.success(), "risp crashed running {name}");
+        outputs.push(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+
+    fs::remove_file(&path).unwrap();
+    outputs
+}
+
+#[test]
+fn call_depth() {
+    let src = "(defn f (n) (+ 1 (f n))) (f 1)";
+    for stderr in run("depth", src, &[]) {
+        assert!(stderr.contains("error[RecursionError]: maximum call depth of 1000 exceeded"));
+    }
+
+    for stderr in run("depth", src, &["--max-depth", "50"]) {
+        assert!(stderr.contains("maximum call depth of 50 exceeded"));
+    }
+}
+
+#[test]
+fn steps() {
+    for stderr in run("steps", "(while true 1)", &["--max-steps", "1000"]) {
+        assert!(stderr.contains("error[LimitExceeded]: evaluation step limit of 1000 exceeded"));
+    }
+}
+
+#[test]
+fn loop_steps() {
+    let programs = [
+        "(dotimes (i 9000000000000000000))",
+        "(for (x (range 9000000000000000000)))",
+        "(for (x (range 9000000000000000000)) (continue))",
+        "(each + (range 9000000000000000000))",
+        "(while true)",
+    ];
+
+    for src in programs {
+        for stderr in run("loop-steps", src, &["--max-steps", "1000"]) {
+            assert!(
+                stderr.contains("error[LimitExceeded]: evaluation step limit of 1000 exceeded"),
+                "{src}: {stderr}"
+            );
+        }
+    }
+}
+
+#[test]
+fn size() {
+    let src = r#"(* "abc" 1000000000) (list 1 2 3 4 5) (+ "abc" "def")"#;
+    for stderr in run("size", src, &["--max-size", "4"]) {
+        assert!(stderr.contains("error[MemoryError]: size of 3000000000 exceeds the limit of 4"));
+        assert!(stderr.contains("error[MemoryError]: size of 5 exceeds the limit of 4"));
+        assert!(stderr.contains("error[MemoryError]: size of 6 exceeds the limit of 4"));
+    }
+}
+
+#[test]
+fn spread_ranges() {
+    let src = "(apply + (range 3000000000)) `(+ ,@(range 3000000000))";
+    for stderr in run("spread-ranges", src, &["--max-size", "1000"]) {
+        assert_eq!(stderr.matches("error[MemoryError]: size of 1001 exceeds the limit of 1000").count(), 2);
+    }
+
+    for stderr in run("spread-ranges", "(apply + (range 3000000000))", &["--max-steps", "1000"]) {
+        assert!(stderr.contains("error[LimitExceeded]: evaluation step limit of 1000 exceeded"));
+    }
+}
+
+#[test]
+fn default_size() {
+    let src = r#"(* "ab" 9000000000000000000) (* "a" 100000000000000) (println (* "ab" 3))"#;
+    for stderr in run("default-size", src, &[]) {
+        assert!(stderr.contains("size of 18000000000000000000 exceeds the limit of 100000000"));
+        assert!(stderr.contains("size of 100000000000000 exceeds the limit of 100000000"));
+    }
+}
+
+#[test]
+fn ratio_size() {
+    let src = "(** (/ 3 2) 3000000) (** 2 -3000000) (/ (** 2 700) (** 3 300))";
+    for stderr in run("ratio-size", src, &["--max-size", "100"]) {
+        assert!(stderr.contains("error[MemoryError]: size of 375000 exceeds the limit of 100"));
+        assert_eq!(stderr.matches("size of 375000").count(), 2);
+        assert!(stderr.contains("error[MemoryError]: size of 147 exceeds the limit of 100"));
+    }
+}
+
+#[test]
+fn invalid_flags() {
+    for flags in [&["--max-steps"][..], &["--max-depth", "deep"], &["--max-size", "-1", "x.risp"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_risp"))
+            .args(flags)
+            .stdin(Stdio::null())
+            .output()
+            .expect("could not run risp");
+
+        assert_eq!(output.status.code(), Some(2));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.starts_with("error: "), "{stderr}");
+        assert!(stderr.contains("usage: risp"));
+    }
+}
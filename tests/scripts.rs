@@ -0,0 +1,86 @@
+//! Runs the scripts in the `scripts` directory which have an expected
+//! output, and checks that both engines print exactly that output. The
+//! expected output of `name.risp` is stored in `name.out`, and the
+//! errors that it reports, if any, in `name.err`.
+//!
+//! The bytecode engine gives no frames to the special forms that it
+//! lowers, so where its errors differ from the tree-walker's, they are
+//! stored in `name.vm.err`. They may only differ in those frames, and in
+//! how many frames the repeated parts of a traceback are made of.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The special forms that the bytecode compiler lowers
+const LOWERED_FORMS: &[&str] = &["if", "block", "while", "list", "set", "define", "set!", "let"];
+
+/// Runs a script with one of the engines, returning its STDOUT and STDERR
+fn run(script: &Path, tree_walk: bool) -> (String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_risp"))
+        .args(tree_walk.then_some("--tree-walk"))
+        .arg(script)
+        .stdin(Stdio::null())
+        .output()
+        .expect("could not run risp");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    (stdout, stderr)
+}
+
+/// Leaves out the frames of lowered special forms, each of which is a
+/// line with the location and name followed by a line of code, and the
+/// lines that say how often frames were repeated. Tracebacks that are
+/// left without frames are left out too.
+fn without_lowered_frames(errors: &str) -> Vec<&str> {
+    let mut kept: Vec<&str> = Vec::new();
+    let mut lines = errors.lines();
+    while let Some(line) = lines.next() {
+        let frame = line.strip_prefix("  ").and_then(|line| line.rsplit_once(", in "));
+        if frame.is_some_and(|(_, name)| LOWERED_FORMS.contains(&name)) {
+            lines.next();
+            continue;
+        }
+        if line.trim_start().starts_with("[Previous ") {
+            continue;
+        }
+
+        if line.starts_with("error[") && kept.last() == Some(&"Traceback (most recent call last):") {
+            kept.pop();
+        }
+        kept.push(line);
+    }
+    kept
+}
+
+#[test]
+fn expected_output() {
+    let mut scripts: Vec<PathBuf> = fs::read_dir("scripts")
+        .expect("could not read scripts directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "risp"))
+        .filter(|path| path.with_extension("out").exists())
+        .collect();
+    scripts.sort();
+
+    assert!(!scripts.is_empty());
+    for script in scripts {
+        let expected = fs::read_to_string(script.with_extension("out")).unwrap();
+        let errors = fs::read_to_string(script.with_extension("err")).unwrap_or_default();
+        let vm_errors = fs::read_to_string(script.with_extension("vm.err")).unwrap_or_else(|_| errors.clone());
+
+        assert_eq!(
+            without_lowered_frames(&vm_errors),
+            without_lowered_frames(&errors),
+            "the errors of the engines differ in more than frames for {}",
+            script.display()
+        );
+
+        for (tree_walk, errors) in [(false, &vm_errors), (true, &errors)] {
+            let (stdout, stderr) = run(&script, tree_walk);
+            assert_eq!(stdout, expected, "unexpected output from {}", script.display());
+            assert_eq!(&stderr, errors, "unexpected errors from {}", script.display());
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Checks how source code is read: string, number and collection
+//! literals and comments, and the syntax errors for malformed ones.
+
+use std::fs;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, process};
+
+/// Runs code with the interpreter, returning its STDOUT and STDERR
+fn run(src: &str) -> (String, String) {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let run = RUNS.fetch_add(1, Ordering::Relaxed);
+    let path = env::temp_dir().join(format!("risp-syntax-{}-{run}.risp", process::id()));
+    fs::write(&path, src).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_risp"))
+        .arg(&path)
+        .stdin(Stdio::null())
+        .output()
+        .expect("could not run risp");
+    fs::remove_file(&path).unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    (stdout, stderr)
+}
+
+/// Evaluates code, and returns its last value as `print` shows it
+fn eval(src: &str) -> String {
+    let (stdout, stderr) = run(&format!("(print (block\n{src}\n))"));
+    assert!(stderr.is_empty(), "{src} failed:\n{stderr}");
+    stdout
+}
+
+/// Returns the message of the syntax error that code produces, along
+/// with the line and column it points at, like `unclosed parenthesis
+/// at 2:1`
+fn syntax_error(src: &str) -> String {
+    let (_, stderr) = run(src);
+    let mut lines = stderr.split('\n');
+
+    let msg = lines.next().and_then(|line| line.strip_prefix("error[SyntaxError]: "));
+    let location = lines.next().and_then(|line| {
+        let mut parts = line.rsplitn(3, ':');
+        Some((parts.next()?, parts.next()?))
+    });
+
+    match (msg, location) {
+        (Some(msg), Some((col, line))) => format!("{msg} at {line}:{col}"),
+        _ => panic!("expected a syntax error from {src}, found {stderr:?}"),
+    }
+}
+
+#[test]
+fn escape_sequences() {
+    assert_eq!(eval(r#""a\tb\nc\r\0""#), "a\tb\nc\r\0");
+    assert_eq!(eval(r#""\\ \" \'""#), r#"\ " '"#);
+    assert_eq!(eval(r#""\u{41}\u{e9}\u{1F600}""#), "Aé😀");
+    assert_eq!(eval("\"é✓\""), "é✓");
+
+    assert_eq!(syntax_error(r#"(+ "a\qb")"#), "unknown escape sequence \\q at 1:6");
+    assert_eq!(syntax_error(r#""\u41""#), "expected `{` after \\u at 1:2");
+    assert_eq!(syntax_error(r#""\u{41""#), "malformed unicode escape at 1:2");
+    assert_eq!(syntax_error(r#""\u{D800}""#), "invalid unicode escape at 1:2");
+}
+
+#[test]
+fn raw_strings() {
+    assert_eq!(eval(r#"r"C:\path\n""#), r"C:\path\n");
+    assert_eq!(eval(r##"r#"say "hi""#"##), r#"say "hi""#);
+    assert_eq!(eval(r###"r##"a "# b"##"###), r##"a "# b"##);
+    assert_eq!(eval(r#"r"""#), "");
+
+    // Names that start with `r` are not raw strings
+    assert_eq!(eval("(define r 1) (define rest 2) (+ r rest)"), "3");
+}
+
+#[test]
+fn line_continuations() {
+    assert_eq!(eval("\"a\\\n    b\""), "ab");
+    assert_eq!(eval("\"a\\\r\n    b\""), "ab");
+
+    // A carriage return on its own is not a newline
+    assert_eq!(syntax_error("\"a\\\rb\""), "unknown escape sequence \\\r at 1:3");
+}
+
+#[test]
+fn unterminated_strings() {
+    for src in [r#""abc"#, r#""abc\""#, r#"r"abc"#, r##"r#"abc""##, "\"\\"] {
+        assert_eq!(syntax_error(src), "unterminated string starting at line 1 at 1:1", "{src}");
+    }
+
+    // The error points at the opening quote
+    let err = syntax_error("(println \"a\")\n(println \"b)");
+    assert_eq!(err, "unterminated string starting at line 2 at 2:10");
+}
+
+#[test]
+fn line_comments() {
+    assert_eq!(eval("; a comment\n(list 1 ; another\n 2) ; at the end"), "[1 2]");
+    assert_eq!(eval("\"a;b\" ;"), "a;b");
+    assert_eq!(eval("; only a comment"), "null");
+}
+
+#[test]
+fn block_comments() {
+    assert_eq!(eval("(list 1 #| a\n multiline (comment |# 2)"), "[1 2]");
+    assert_eq!(eval("(list 1#|x|#2)"), "[1 2]");
+    assert_eq!(eval(r##""#|not a comment|#""##), "#|not a comment|#");
+
+    // Block comments nest, so inner delimiters do not end them
+    assert_eq!(eval("(list 1 #| a #| b |# c |# 2)"), "[1 2]");
+    assert_eq!(eval("#| #| #| deep |# |# |# (list 3)"), "[3]");
+
+    let err = syntax_error("(list 1)\n#| a #| b |# c");
+    assert_eq!(err, "unterminated block comment starting at line 2 at 2:1");
+}
+
+#[test]
+fn datum_comments() {
+    assert_eq!(eval("(list 1 #; (+ 2 3) 4)"), "[1 4]");
+    assert_eq!(eval("(list 1 #;2)"), "[1]");
+    assert_eq!(eval("#; (println \"never\") (list 1)"), "[1]");
+
+    // Stacked datum comments each comment out one expression
+    assert_eq!(eval("(list #; #; 1 2 3)"), "[3]");
+    assert_eq!(eval("(list #; {1 #; 2 3} 4)"), "[4]");
+
+    let err = syntax_error("(list 1) #;");
+    assert_eq!(err, "expected an expression after datum comment at 1:10");
+}
+
+#[test]
+fn int_literals() {
+    for (src, expected) in [
+        ("0xff", "255"),
+        ("-0x10", "-16"),
+        ("0o17", "15"),
+        ("0b1010", "10"),
+        ("+7", "7"),
+        ("1_000_000", "1000000"),
+        ("0x_ff_ff", "65535"),
+        ("-9223372036854775808", "-9223372036854775808"),
+    ] {
+        assert_eq!(eval(src), expected, "{src}");
+    }
+
+    // Ints that do not fit in 64 bits become big ints
+    assert_eq!(eval("(+ 1 99999999999999999999)"), "100000000000000000000");
+    assert_eq!(eval("0xffffffffffffffffff"), "4722366482869645213695");
+}
+
+#[test]
+fn float_literals() {
+    for (src, expected) in [
+        ("1.5", "1.5"),
+        (".5", "0.5"),
+        ("-.5", "-0.5"),
+        ("1.", "1"),
+        ("1e3", "1000"),
+        ("1E+2", "100"),
+        ("1.5e-3", "0.0015"),
+        ("1_000.000_1", "1000.0001"),
+        ("1e-999", "0"),
+    ] {
+        assert_eq!(eval(src), expected, "{src}");
+    }
+}
+
+#[test]
+fn invalid_number_literals() {
+    for src in ["1.2.3", "0x", "0b102", "0o8", "0x-1", "1e", "1e+", "1e3.5", "12abc", "-1.2.3"] {
+        assert_eq!(syntax_error(src), format!("invalid number literal {src:?} at 1:1"));
+    }
+
+    assert_eq!(syntax_error("(+ 1 1e999)"), "float literal 1e999 is out of range at 1:6");
+    assert_eq!(syntax_error("-1e999"), "float literal -1e999 is out of range at 1:1");
+
+    // Names may contain digits, as long as they do not start with one
+    assert_eq!(eval("(define x1 1) (define -x 2) (+ x1 -x)"), "3");
+}
+
+#[test]
+fn collection_literals() {
+    assert_eq!(eval(r#"{"a" 1 "b" (+ 1 1)}"#), r#"{"a" 1, "b" 2}"#);
+    assert_eq!(eval(r#"{"a" 1 "a" 2}"#), r#"{"a" 2}"#);
+    assert_eq!(eval("#{1 2 2 3}"), "#{1 2 3}");
+    assert_eq!(eval("{}"), "{}");
+    assert_eq!(eval("#{}"), "#{}");
+
+    assert_eq!(syntax_error("{1}"), "map literal must have an even number of elements at 1:1");
+    assert_eq!(syntax_error("#{1"), "unclosed brace at 1:1");
+    assert_eq!(syntax_error("#{1 2}}"), "unexpected CloseBrace while parsing atom at 1:7");
+}